@@ -0,0 +1,14 @@
+#![no_main]
+
+use iridium::vm::VM;
+use libfuzzer_sys::fuzz_target;
+
+/// Upper bound on executed instructions so looping programs still finish
+const MAX_STEPS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let mut vm = VM::new().with_heap_limit(64 * 1024);
+    if vm.load_executable(data).is_ok() {
+        vm.run_for(MAX_STEPS);
+    }
+});
@@ -0,0 +1,63 @@
+//! The register calling convention. The VM treats all integer registers alike; the names below
+//! are what the assembler accepts in place of `$0`..`$31` and what the disassembler and REPL
+//! print.
+//!
+//! | Registers   | Names         | Use                                           |
+//! |-------------|---------------|-----------------------------------------------|
+//! | `$0`-`$1`   | `$v0`-`$v1`   | Return values                                 |
+//! | `$2`-`$9`   | `$a0`-`$a7`   | Arguments, not preserved across calls         |
+//! | `$10`-`$21` | `$t0`-`$t11`  | Temporaries, not preserved across calls       |
+//! | `$22`-`$31` | `$s0`-`$s9`   | Saved registers, preserved by the callee      |
+
+use crate::vm::REGISTER_COUNT;
+
+/// ABI name of every integer register, indexed by register number
+const NAMES: [&str; REGISTER_COUNT] = [
+    "v0", "v1", "a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7", "t0", "t1", "t2", "t3", "t4", "t5",
+    "t6", "t7", "t8", "t9", "t10", "t11", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8",
+    "s9",
+];
+
+/// Returns the ABI name of integer register `reg_num`, without the leading `$`
+pub fn register_name(reg_num: u8) -> Option<&'static str> {
+    NAMES.get(reg_num as usize).copied()
+}
+
+/// Returns the number of the integer register written as `name` without the leading `$`:
+/// either its number, such as `3`, or its ABI name, such as `a1`
+pub fn register_number(name: &str) -> Option<u8> {
+    let index = match name.parse::<usize>() {
+        Ok(number) => number,
+        Err(_) => NAMES.iter().position(|abi_name| *abi_name == name)?,
+    };
+    (index < REGISTER_COUNT).then_some(index as u8)
+}
+
+/// Returns the number of the float register written as `name` without the leading `$`, such
+/// as `f3`
+pub fn float_register_number(name: &str) -> Option<u8> {
+    let index: usize = name.strip_prefix('f')?.parse().ok()?;
+    (index < REGISTER_COUNT).then_some(index as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_names_round_trip() {
+        for reg_num in 0..REGISTER_COUNT as u8 {
+            let name = register_name(reg_num).unwrap();
+            assert_eq!(register_number(name), Some(reg_num));
+            assert_eq!(register_number(&reg_num.to_string()), Some(reg_num));
+        }
+        assert_eq!(register_name(32), None);
+        assert_eq!(register_number("a0"), Some(2));
+        assert_eq!(register_number("s9"), Some(31));
+        assert_eq!(register_number("32"), None);
+        assert_eq!(register_number("t12"), None);
+        assert_eq!(float_register_number("f31"), Some(31));
+        assert_eq!(float_register_number("f32"), None);
+        assert_eq!(float_register_number("3"), None);
+    }
+}
@@ -0,0 +1,382 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::assembler::opcode::OperandKind;
+use crate::instruction::Opcode;
+
+/// The most macro expansion notes printed with one error
+const MAX_MACRO_NOTES: usize = 8;
+
+/// Where a statement was found in the assembler's input
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub file: String,
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number, starting at 1
+    pub column: usize,
+    /// The full text of the line
+    pub snippet: String,
+    /// The macro calls the line was expanded from, innermost first
+    pub macro_calls: Vec<MacroCall>,
+    /// Position of the line in the preprocessed source, which puts errors in the order the
+    /// assembler reached them
+    pub sequence: usize,
+}
+
+/// A macro call that a line of source was expanded from
+#[derive(Clone, Debug, PartialEq)]
+pub struct MacroCall {
+    pub name: String,
+    pub location: SourceLocation,
+}
+
+/// The kind of problem found while assembling a program
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssemblerErrorKind {
+    /// The source could not be parsed
+    ParseError { error: String },
+    /// A directive name that the assembler does not know
+    UnknownDirective { name: String },
+    /// A directive was given operands it cannot use
+    InvalidDirectiveOperands {
+        directive: String,
+        expected: &'static str,
+    },
+    /// An instruction appeared in the data section
+    InstructionOutsideCode,
+    /// A data directive appeared in the code section
+    DataOutsideData { directive: String },
+    /// A literal or label address does not fit in the operand or data item it is used for
+    ValueOutOfRange { value: i64, min: i64, max: i64 },
+    /// A source file could not be read
+    ReadFailed { path: String, error: String },
+    /// A file included itself, directly or through other includes
+    IncludeCycle { path: String },
+    /// A `.macro` was never closed with `.endm`
+    UnterminatedMacro { name: String },
+    /// An `.endm` appeared outside of a macro definition
+    UnmatchedEndm,
+    /// A `.macro` appeared inside another macro's definition
+    NestedMacro,
+    /// The same macro was defined more than once
+    DuplicateMacro { name: String },
+    /// A macro was called with the wrong number of arguments
+    MacroArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Macros called each other more deeply than the assembler allows
+    MacroRecursionLimit { name: String, limit: usize },
+    /// An expression named a constant that is not defined, or not defined early enough
+    UnknownConstant { name: String },
+    /// The same constant was defined more than once
+    DuplicateConstant { name: String },
+    /// An expression overflowed 64-bit arithmetic or shifted by an invalid amount
+    ExpressionOverflow,
+    /// An expression divided by zero
+    DivideByZero,
+    /// A `$name` operand is not a register number below 32, an ABI name or an alias
+    UnknownRegister { name: String },
+    /// The same alias was defined more than once in a file
+    DuplicateAlias { name: String },
+    /// An alias has the name of a register, which it would hide
+    AliasShadowsRegister { name: String },
+    /// The opcode field of an instruction held something other than an opcode
+    ExpectedOpcode,
+    /// An `@label` operand named a label that is never declared
+    UnknownLabel { name: String },
+    /// A label outside the code section was used where an instruction address is needed
+    DataLabelAsCodeAddress { name: String },
+    /// An expression subtracted labels from different sections
+    LabelSectionMismatch { lhs: String, rhs: String },
+    /// The same label was declared more than once
+    DuplicateLabel { name: String },
+    /// A label name is too long to be written to an executable's symbol section
+    LabelTooLong { name: String, max: usize },
+    /// More than one `.entry` directive was given
+    DuplicateEntry,
+    /// The mnemonic does not name any instruction
+    UnknownOpcode,
+    /// The instruction was given the wrong number of operands
+    IncorrectOperandCount {
+        opcode: Opcode,
+        expected: usize,
+        found: usize,
+    },
+    /// An operand (numbered from 1) has the wrong shape for the instruction
+    OperandMismatch {
+        opcode: Opcode,
+        position: usize,
+        expected: OperandKind,
+    },
+}
+
+/// A problem found in a statement, with the operand it is about, if it is about one
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatementError {
+    pub kind: AssemblerErrorKind,
+    /// Index of the operand, starting at 0
+    pub operand: Option<usize>,
+}
+
+impl StatementError {
+    /// Returns a function that attributes an error to operand `index`, for use with `map_err`
+    pub fn in_operand(index: usize) -> impl Fn(AssemblerErrorKind) -> StatementError {
+        move |kind| StatementError {
+            kind,
+            operand: Some(index),
+        }
+    }
+}
+
+impl From<AssemblerErrorKind> for StatementError {
+    fn from(kind: AssemblerErrorKind) -> StatementError {
+        StatementError {
+            kind,
+            operand: None,
+        }
+    }
+}
+
+/// A problem found while assembling a program, with the place in the source it refers to
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssemblerError {
+    pub kind: AssemblerErrorKind,
+    pub file: String,
+    /// Line number, starting at 1, or 0 if the error is about the whole file
+    pub line: usize,
+    /// Column number, starting at 1
+    pub column: usize,
+    /// The full text of the offending line
+    pub snippet: String,
+    /// The macro calls the offending line was expanded from, innermost first
+    pub macro_calls: Vec<MacroCall>,
+    /// Position of the offending line in the preprocessed source
+    pub sequence: usize,
+}
+
+impl AssemblerError {
+    pub fn new(kind: AssemblerErrorKind, location: &SourceLocation) -> AssemblerError {
+        AssemblerError {
+            kind,
+            file: location.file.clone(),
+            line: location.line,
+            column: location.column,
+            snippet: location.snippet.clone(),
+            macro_calls: location.macro_calls.clone(),
+            sequence: location.sequence,
+        }
+    }
+
+    /// Describes the problem without its location
+    pub fn message(&self) -> String {
+        self.kind.to_string()
+    }
+}
+
+impl fmt::Display for AssemblerErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssemblerErrorKind::ParseError { error } => write!(f, "unable to parse: {}", error),
+            AssemblerErrorKind::UnknownDirective { name } => {
+                write!(f, "unknown directive .{}", name)
+            }
+            AssemblerErrorKind::InvalidDirectiveOperands {
+                directive,
+                expected,
+            } => write!(f, ".{} expects {}", directive, expected),
+            AssemblerErrorKind::InstructionOutsideCode => {
+                write!(f, "instructions must be in the .code section")
+            }
+            AssemblerErrorKind::DataOutsideData { directive } => {
+                write!(f, ".{} must be in the .data section", directive)
+            }
+            AssemblerErrorKind::ValueOutOfRange { value, min, max } => write!(
+                f,
+                "value {} is out of range (expected {} to {})",
+                value, min, max
+            ),
+            AssemblerErrorKind::ReadFailed { path, error } => {
+                write!(f, "cannot read {}: {}", path, error)
+            }
+            AssemblerErrorKind::IncludeCycle { path } => {
+                write!(f, "{} is already being included", path)
+            }
+            AssemblerErrorKind::UnterminatedMacro { name } => {
+                write!(f, "macro {} is missing .endm", name)
+            }
+            AssemblerErrorKind::UnmatchedEndm => write!(f, ".endm without a matching .macro"),
+            AssemblerErrorKind::NestedMacro => write!(f, "macro definitions cannot be nested"),
+            AssemblerErrorKind::DuplicateMacro { name } => {
+                write!(f, "macro {} is defined more than once", name)
+            }
+            AssemblerErrorKind::MacroArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "macro {} takes {} argument(s) but {} were given",
+                name, expected, found
+            ),
+            AssemblerErrorKind::MacroRecursionLimit { name, limit } => write!(
+                f,
+                "expanding macro {} nests more than {} macro calls deep",
+                name, limit
+            ),
+            AssemblerErrorKind::UnknownConstant { name } => {
+                write!(f, "constant {} is not defined", name)
+            }
+            AssemblerErrorKind::DuplicateConstant { name } => {
+                write!(f, "constant {} is defined more than once", name)
+            }
+            AssemblerErrorKind::ExpressionOverflow => write!(f, "expression overflows"),
+            AssemblerErrorKind::DivideByZero => write!(f, "division by zero in expression"),
+            AssemblerErrorKind::UnknownRegister { name } => {
+                write!(f, "unknown register ${}", name)
+            }
+            AssemblerErrorKind::DuplicateAlias { name } => {
+                write!(f, "alias {} is defined more than once", name)
+            }
+            AssemblerErrorKind::AliasShadowsRegister { name } => {
+                write!(f, "alias {} has the name of register ${}", name, name)
+            }
+            AssemblerErrorKind::ExpectedOpcode => write!(f, "expected an opcode"),
+            AssemblerErrorKind::UnknownLabel { name } => write!(f, "undefined label @{}", name),
+            AssemblerErrorKind::DataLabelAsCodeAddress { name } => {
+                write!(
+                    f,
+                    "@{} is not a code label but is used as a code address",
+                    name
+                )
+            }
+            AssemblerErrorKind::LabelSectionMismatch { lhs, rhs } => write!(
+                f,
+                "cannot subtract @{} from @{} because they are in different sections",
+                rhs, lhs
+            ),
+            AssemblerErrorKind::DuplicateLabel { name } => {
+                write!(f, "label {} is declared more than once", name)
+            }
+            AssemblerErrorKind::LabelTooLong { name, max } => write!(
+                f,
+                "label {} is {} bytes long, more than the limit of {}",
+                name,
+                name.len(),
+                max
+            ),
+            AssemblerErrorKind::DuplicateEntry => {
+                write!(f, "the entry point is declared more than once")
+            }
+            AssemblerErrorKind::UnknownOpcode => write!(f, "unknown opcode"),
+            AssemblerErrorKind::IncorrectOperandCount {
+                opcode,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{:?} takes {} operand(s) but {} were given",
+                opcode, expected, found
+            ),
+            AssemblerErrorKind::OperandMismatch {
+                opcode,
+                position,
+                expected,
+            } => write!(
+                f,
+                "operand {} of {:?} must be a {}",
+                position, opcode, expected
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Errors about a whole file, such as failing to read it, have no line
+        if self.line == 0 {
+            return write!(f, "{}: error: {}", self.file, self.kind);
+        }
+        write!(
+            f,
+            "{}:{}:{}: error: {}",
+            self.file, self.line, self.column, self.kind
+        )?;
+        write_snippet(f, self.line, self.column, &self.snippet)?;
+        // A runaway macro repeats the same call site up to the nesting limit, so consecutive
+        // identical calls share one note and only the first few notes are printed
+        let mut frames: Vec<(&MacroCall, usize)> = Vec::new();
+        for call in &self.macro_calls {
+            match frames.last_mut() {
+                Some((last, count)) if *last == call => *count += 1,
+                _ => frames.push((call, 1)),
+            }
+        }
+        for (call, count) in frames.iter().take(MAX_MACRO_NOTES) {
+            let location = &call.location;
+            write!(
+                f,
+                "\n{}:{}:{}: note: in expansion of macro {}",
+                location.file, location.line, location.column, call.name
+            )?;
+            if *count > 1 {
+                write!(f, " ({} times)", count)?;
+            }
+            write_snippet(f, location.line, location.column, &location.snippet)?;
+        }
+        if frames.len() > MAX_MACRO_NOTES {
+            let hidden: usize = frames[MAX_MACRO_NOTES..]
+                .iter()
+                .map(|(_, count)| count)
+                .sum();
+            write!(f, "\nnote: ... {} more macro expansions", hidden)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a line of source with a caret under `column`
+fn write_snippet(f: &mut fmt::Formatter, line: usize, column: usize, snippet: &str) -> fmt::Result {
+    let gutter = line.to_string().len();
+    write!(f, "\n{} | {}", line, snippet)?;
+    write!(
+        f,
+        "\n{:gutter$} | {:>column$}",
+        "",
+        "^",
+        gutter = gutter,
+        column = column
+    )
+}
+
+impl Error for AssemblerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_points_at_column() {
+        let location = SourceLocation {
+            file: "loop.iasm".to_string(),
+            line: 12,
+            column: 9,
+            snippet: "jmp $0 @nowhere".to_string(),
+            macro_calls: vec![],
+            sequence: 0,
+        };
+        let error = AssemblerError::new(
+            AssemblerErrorKind::UnknownLabel {
+                name: "nowhere".to_string(),
+            },
+            &location,
+        );
+        assert_eq!(
+            error.to_string(),
+            "loop.iasm:12:9: error: undefined label @nowhere\n12 | jmp $0 @nowhere\n   |         ^"
+        );
+        assert_eq!(error.message(), "undefined label @nowhere");
+    }
+}
@@ -0,0 +1,356 @@
+use crate::assembler::assembler_errors::{AssemblerErrorKind, StatementError};
+use crate::assembler::label_parsers::{label_declaration, label_name, label_usage};
+use crate::assembler::opcode::check_range;
+use crate::assembler::operand_parsers::{float_operand, integer_operand, located, string_operand};
+use crate::assembler::symbols::{Section, SymbolTable};
+use crate::assembler::Token;
+
+use nom::{
+    branch::alt,
+    character::complete::{alphanumeric1, char, space0, space1},
+    combinator::{map, opt, value},
+    multi::separated_list1,
+    sequence::{delimited, preceded, terminated, tuple},
+    IResult,
+};
+
+/// Largest size `.space` accepts: the most data a 16-bit label offset can address
+pub const MAX_SPACE: usize = 1 << 16;
+
+const SPACE_OPERANDS: &str = "a single byte count from 0 to 65536";
+
+/// A directive such as `.asciiz "hello"` or `.data`, with an optional label
+#[derive(Debug, PartialEq)]
+pub struct Directive {
+    label: Option<Token>,
+    name: Token,
+    operands: Vec<Token>,
+    /// For each operand, the length of the line from the operand's start
+    operand_positions: Vec<usize>,
+}
+
+/// Parses an optional label declaration followed by a directive and its operands. Operands are
+/// separated by spaces or commas.
+pub fn directive(input: &str) -> IResult<&str, Directive> {
+    let (input, (label, name, operands)) = tuple((
+        opt(terminated(label_declaration, space0)),
+        directive_name,
+        opt(preceded(
+            space1,
+            separated_list1(operand_separator, located(directive_operand)),
+        )),
+    ))(input)?;
+    let (operands, operand_positions) = operands.unwrap_or_default().into_iter().unzip();
+
+    Ok((
+        input,
+        Directive {
+            label,
+            name,
+            operands,
+            operand_positions,
+        },
+    ))
+}
+
+fn directive_name(input: &str) -> IResult<&str, Token> {
+    let (input, name) = preceded(char('.'), alphanumeric1)(input)?;
+
+    Ok((
+        input,
+        Token::Directive {
+            name: name.to_string(),
+        },
+    ))
+}
+
+/// Parses the separator between operands: a comma with optional spaces, or just spaces
+pub fn operand_separator(input: &str) -> IResult<&str, char> {
+    alt((delimited(space0, char(','), space0), value(' ', space1)))(input)
+}
+
+fn directive_operand(input: &str) -> IResult<&str, Token> {
+    alt((
+        float_operand,
+        integer_operand,
+        string_operand,
+        label_usage,
+        map(label_name, |name| Token::Identifier {
+            name: name.to_string(),
+        }),
+    ))(input)
+}
+
+/// Returns the value of an integer, expression or label operand, or `None` for any other token
+fn operand_value(
+    operand: &Token,
+    symbols: &SymbolTable,
+) -> Option<Result<i64, AssemblerErrorKind>> {
+    match operand {
+        Token::IntegerOperand { value } => Some(Ok(*value)),
+        Token::Expression { expr } => Some(expr.evaluate(symbols)),
+        Token::LabelUsage { name } => Some(symbols.label(name).map(|symbol| symbol.offset as i64)),
+        _ => None,
+    }
+}
+
+impl Directive {
+    /// Returns the name of the label declared on this directive, if any
+    pub fn label_name(&self) -> Option<&str> {
+        match &self.label {
+            Some(Token::LabelDeclaration { name }) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the directive's name without the leading `.`
+    pub fn name(&self) -> &str {
+        match &self.name {
+            Token::Directive { name } => name,
+            _ => "",
+        }
+    }
+
+    /// Returns the section this directive switches to, if it is `.code` or `.data`
+    pub fn section(&self) -> Option<Section> {
+        match self.name() {
+            "code" => Some(Section::Code),
+            "data" => Some(Section::Data),
+            _ => None,
+        }
+    }
+
+    /// Returns the section this directive's contents and label go in whatever the current
+    /// section is: the constant pool for `.double`
+    pub fn placement(&self) -> Option<Section> {
+        match self.name() {
+            "double" => Some(Section::Constants),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` for the directives that emit data, which belong in the data section
+    pub fn is_data(&self) -> bool {
+        matches!(self.name(), "byte" | "half" | "word" | "asciiz" | "space")
+    }
+
+    /// Number of bytes this directive adds to the data section, or for `.double`, the number of
+    /// entries it adds to the constant pool. The size given to `.space` may be an expression,
+    /// which can only use constants and labels already in `symbols`.
+    pub fn size(&self, symbols: &SymbolTable) -> Result<usize, StatementError> {
+        Ok(match (self.name(), self.operands.as_slice()) {
+            ("byte", operands) => operands.len(),
+            ("half", operands) => operands.len() * 2,
+            ("word", operands) => operands.len() * 4,
+            ("double", operands) => operands.len(),
+            ("asciiz", [Token::StringOperand { value }]) => value.len() + 1,
+            ("space", [_]) => self.space(symbols)?,
+            _ => 0,
+        })
+    }
+
+    /// Returns the name and value defined by an `.equ` directive, evaluated with the constants
+    /// and labels already in `symbols`
+    pub fn constant(&self, symbols: &SymbolTable) -> Result<Option<(&str, i64)>, StatementError> {
+        match (self.name(), self.operands.as_slice()) {
+            ("equ", [Token::Identifier { name }, operand]) => match operand_value(operand, symbols)
+            {
+                Some(value) => Ok(Some((name, value.map_err(StatementError::in_operand(1))?))),
+                None => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the label named by an `.entry @label` directive
+    pub fn entry_label(&self) -> Option<&str> {
+        match (self.name(), self.operands.as_slice()) {
+            ("entry", [Token::LabelUsage { name }]) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns, for each operand, the length of the line from the operand's start
+    pub fn operand_positions(&self) -> &[usize] {
+        &self.operand_positions
+    }
+
+    /// Encodes the data this directive emits. `.double` emits each constant as 8 big-endian
+    /// bytes. Section directives, `.equ` and `.entry` emit nothing.
+    pub fn to_bytes(&self, symbols: &SymbolTable) -> Result<Vec<u8>, StatementError> {
+        let invalid = |expected| AssemblerErrorKind::InvalidDirectiveOperands {
+            directive: self.name().to_string(),
+            expected,
+        };
+        let mut results = vec![];
+        match (self.name(), self.operands.as_slice()) {
+            ("code" | "data", []) => {}
+            ("code" | "data", _) => return Err(invalid("no operands").into()),
+            ("byte", [_, ..]) => self.extract_values(1, &mut results, symbols)?,
+            ("half", [_, ..]) => self.extract_values(2, &mut results, symbols)?,
+            ("word", [_, ..]) => self.extract_values(4, &mut results, symbols)?,
+            ("byte" | "half" | "word", []) => return Err(invalid("at least one value").into()),
+            ("double", [_, ..]) => {
+                for (index, operand) in self.operands.iter().enumerate() {
+                    let value = match operand {
+                        Token::FloatOperand { value } => *value,
+                        Token::IntegerOperand { value } => *value as f64,
+                        _ => {
+                            let invalid = invalid("floating-point numbers");
+                            return Err(StatementError::in_operand(index)(invalid));
+                        }
+                    };
+                    results.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            ("double", []) => return Err(invalid("at least one floating-point number").into()),
+            ("asciiz", [Token::StringOperand { value }]) => {
+                results.extend_from_slice(value.as_bytes());
+                results.push(0);
+            }
+            ("asciiz", _) => return Err(invalid("a single string").into()),
+            ("space", [_]) => results.resize(self.space(symbols)?, 0),
+            ("space", _) => return Err(invalid(SPACE_OPERANDS).into()),
+            ("equ", [Token::Identifier { .. }, operand])
+                if operand_value(operand, symbols).is_some() => {}
+            ("equ", _) => return Err(invalid("a name and a value").into()),
+            ("entry", [Token::LabelUsage { .. }]) => {}
+            ("entry", _) => return Err(invalid("a label in the code section").into()),
+            (name, _) => {
+                return Err(AssemblerErrorKind::UnknownDirective {
+                    name: name.to_string(),
+                }
+                .into())
+            }
+        }
+        Ok(results)
+    }
+
+    /// Returns the byte count given to `.space`, which must be from 0 to `MAX_SPACE`
+    fn space(&self, symbols: &SymbolTable) -> Result<usize, StatementError> {
+        let invalid = || AssemblerErrorKind::InvalidDirectiveOperands {
+            directive: self.name().to_string(),
+            expected: SPACE_OPERANDS,
+        };
+        let in_operand = StatementError::in_operand(0);
+        let value = match self.operands.as_slice() {
+            [operand] => operand_value(operand, symbols)
+                .ok_or_else(invalid)
+                .and_then(|value| value)
+                .map_err(&in_operand)?,
+            _ => return Err(invalid().into()),
+        };
+        usize::try_from(value)
+            .ok()
+            .filter(|size| *size <= MAX_SPACE)
+            .ok_or_else(|| in_operand(invalid()))
+    }
+
+    /// Encodes every operand as a big-endian value `width` bytes wide. A value may be anything
+    /// that fits in `width` bytes as either a signed or an unsigned number.
+    fn extract_values(
+        &self,
+        width: usize,
+        results: &mut Vec<u8>,
+        symbols: &SymbolTable,
+    ) -> Result<(), StatementError> {
+        for (index, operand) in self.operands.iter().enumerate() {
+            let in_operand = StatementError::in_operand(index);
+            let value = match operand_value(operand, symbols) {
+                Some(value) => value.map_err(&in_operand)?,
+                None => {
+                    return Err(in_operand(AssemblerErrorKind::InvalidDirectiveOperands {
+                        directive: self.name().to_string(),
+                        expected: "integers or labels",
+                    }))
+                }
+            };
+            let bits = width as u32 * 8;
+            let value =
+                check_range(value, -(1 << (bits - 1))..=(1 << bits) - 1).map_err(&in_operand)?;
+            results.extend_from_slice(&value.to_be_bytes()[8 - width..]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directive() {
+        let (rest, parsed) = directive("msg: .asciiz \"hi there\" ; greeting").unwrap();
+        assert_eq!(rest, " ; greeting");
+        assert_eq!(parsed.label_name(), Some("msg"));
+        assert_eq!(parsed.name(), "asciiz");
+        assert_eq!(parsed.size(&SymbolTable::new()), Ok(9));
+
+        let (_, parsed) = directive(".byte #1, #2 #3,#4").unwrap();
+        assert_eq!(parsed.operands.len(), 4);
+
+        let (_, parsed) = directive(".data").unwrap();
+        assert_eq!(parsed.section(), Some(Section::Data));
+        assert!(directive("load $0 #1").is_err());
+    }
+
+    #[test]
+    fn test_directive_to_bytes() {
+        let symbols = SymbolTable::new();
+        let bytes = |source| directive(source).unwrap().1.to_bytes(&symbols);
+        assert_eq!(bytes(".byte #1 #-1 #255"), Ok(vec![1, 0xff, 0xff]));
+        assert_eq!(
+            bytes(".byte #256"),
+            Err(StatementError::in_operand(0)(
+                AssemblerErrorKind::ValueOutOfRange {
+                    value: 256,
+                    min: -128,
+                    max: 255
+                }
+            ))
+        );
+        assert_eq!(bytes(".byte #1, #-129").unwrap_err().operand, Some(1));
+        assert_eq!(
+            bytes(".word #0xffff_ffff #-0x8000_0000"),
+            Ok(vec![0xff, 0xff, 0xff, 0xff, 0x80, 0, 0, 0])
+        );
+        assert!(bytes(".word #0x1_0000_0000").is_err());
+        assert!(bytes(".half #-32769").is_err());
+        assert_eq!(bytes(".half #258 #-2"), Ok(vec![1, 2, 0xff, 0xfe]));
+        assert_eq!(bytes(".word #-2"), Ok(vec![0xff, 0xff, 0xff, 0xfe]));
+        assert_eq!(bytes(".asciiz \"ok\""), Ok(vec![b'o', b'k', 0]));
+        assert_eq!(
+            bytes(".double #1.5, #-2"),
+            Ok([1.5f64.to_be_bytes(), (-2f64).to_be_bytes()].concat())
+        );
+        assert!(bytes(".double").is_err());
+        assert!(bytes(".double @pi").is_err());
+        assert_eq!(bytes(".space #3"), Ok(vec![0, 0, 0]));
+        assert_eq!(bytes(".code"), Ok(vec![]));
+        assert_eq!(bytes(".space #(2 * 2)"), Ok(vec![0; 4]));
+        assert_eq!(bytes(".equ SIZE #(1 + 2)"), Ok(vec![]));
+        assert!(bytes(".equ #3").is_err());
+        assert_eq!(bytes(".entry @main"), Ok(vec![]));
+        assert!(bytes(".entry main").is_err());
+        let bad_space = Err(StatementError::in_operand(0)(
+            AssemblerErrorKind::InvalidDirectiveOperands {
+                directive: "space".to_string(),
+                expected: "a single byte count from 0 to 65536",
+            },
+        ));
+        assert_eq!(bytes(".space #-1"), bad_space);
+        assert_eq!(bytes(".space #0x7fffffff"), bad_space);
+        assert_eq!(bytes(".space #65537"), bad_space);
+        assert_eq!(bytes(".space #65536").map(|data| data.len()), Ok(MAX_SPACE));
+        assert!(bytes(".asciiz #1").is_err());
+        assert!(bytes(".word").is_err());
+        assert!(bytes(".data #1").is_err());
+        assert_eq!(
+            bytes(".bogus"),
+            Err(AssemblerErrorKind::UnknownDirective {
+                name: "bogus".to_string()
+            }
+            .into())
+        );
+    }
+}
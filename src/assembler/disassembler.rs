@@ -0,0 +1,118 @@
+use crate::abi::register_name;
+use crate::assembler::opcode::{operand_schema, OperandKind};
+use crate::instruction::Opcode;
+use crate::vm::INSTRUCTION_SIZE;
+
+/// Returns the mnemonic the assembler uses for `opcode`, such as `addf64`
+pub fn mnemonic(opcode: Opcode) -> String {
+    format!("{:?}", opcode).to_lowercase()
+}
+
+/// Decodes one instruction back into assembly, writing integer registers by their ABI names.
+/// A byte that is not an opcode is written as `igl` followed by the raw bytes in a comment.
+pub fn disassemble_instruction(bytes: &[u8; INSTRUCTION_SIZE]) -> String {
+    let opcode = Opcode::from(bytes[0]);
+    if opcode == Opcode::IGL {
+        let raw: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        return format!("igl ; {}", raw.join(" "));
+    }
+
+    let mut text = mnemonic(opcode);
+    let mut operands = &bytes[1..];
+    for kind in operand_schema(opcode) {
+        let (operand, rest) = operands.split_at(kind.size());
+        operands = rest;
+        let operand = match kind {
+            OperandKind::Register => match register_name(operand[0]) {
+                Some(name) => format!("${}", name),
+                None => format!("${}", operand[0]),
+            },
+            OperandKind::FloatRegister => format!("$f{}", operand[0]),
+            OperandKind::Imm8 => format!("#{}", operand[0]),
+            OperandKind::Imm16 => format!("#{}", u16::from_be_bytes([operand[0], operand[1]])),
+            OperandKind::SImm16 => format!("#{}", i16::from_be_bytes([operand[0], operand[1]])),
+        };
+        text.push(' ');
+        text.push_str(&operand);
+    }
+    text
+}
+
+/// Decodes `code` into one line per instruction, each starting with its offset. Bytes left
+/// over at the end that do not make up a whole instruction are listed in a comment.
+pub fn disassemble(code: &[u8]) -> Vec<String> {
+    let chunks = code.chunks_exact(INSTRUCTION_SIZE);
+    let remainder = chunks.remainder();
+    let mut lines: Vec<String> = chunks
+        .enumerate()
+        .map(|(index, bytes)| {
+            let bytes = bytes.try_into().expect("chunks are instruction sized");
+            format!(
+                "{:04x}: {}",
+                index * INSTRUCTION_SIZE,
+                disassemble_instruction(bytes)
+            )
+        })
+        .collect();
+    if !remainder.is_empty() {
+        let raw: Vec<String> = remainder.iter().map(|b| format!("{:02x}", b)).collect();
+        lines.push(format!(
+            "{:04x}: ; trailing bytes {}",
+            code.len() - remainder.len(),
+            raw.join(" ")
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    #[test]
+    fn test_mnemonics_round_trip() {
+        for byte in 0..=u8::MAX {
+            let opcode = Opcode::from(byte);
+            if opcode != Opcode::IGL {
+                assert_eq!(Opcode::from(mnemonic(opcode).as_str()), opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let source = "\
+load $a0 #500
+subi $t0 #-3
+loop: add $a0 $s9 $v0
+loadb $1 $2 #7
+itof $f3 $a1
+load $31 @loop
+hlt";
+        let code = Assembler::new().assemble(source).unwrap().code;
+        let lines = disassemble(&code);
+        assert_eq!(
+            lines,
+            vec![
+                "0000: load $a0 #500",
+                "0004: subi $t0 #-3",
+                "0008: add $a0 $s9 $v0",
+                "000c: loadb $v1 $a0 #7",
+                "0010: itof $f3 $a1",
+                "0014: load $s9 #8",
+                "0018: hlt",
+            ]
+        );
+
+        // The listing assembles back to the same program
+        let listing: Vec<&str> = lines.iter().map(|line| &line[6..]).collect();
+        let reassembled = Assembler::new().assemble(&listing.join("\n")).unwrap();
+        assert_eq!(reassembled.code, code);
+
+        assert_eq!(
+            disassemble(&[0xc8, 1, 2, 3, Opcode::HLT as u8]),
+            vec!["0000: igl ; c8 01 02 03", "0004: ; trailing bytes 05"]
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use crate::assembler::assembler_errors::AssemblerErrorKind;
+use crate::assembler::label_parsers::{label_name, label_usage};
+use crate::assembler::operand_parsers::number;
+use crate::assembler::symbols::SymbolTable;
+use crate::assembler::Token;
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, space0},
+    combinator::map,
+    sequence::{delimited, preceded},
+    IResult,
+};
+
+/// A compile-time integer expression such as `(BUF_SIZE * 4 + 1)` or `(@end - @start)`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    /// A name defined with `.equ`
+    Constant(String),
+    /// The offset of a label within its section
+    Label(String),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// Binary operators from the loosest to the tightest binding, as in C
+const PRECEDENCE: [&[(&str, BinaryOp)]; 6] = [
+    &[("|", BinaryOp::Or)],
+    &[("^", BinaryOp::Xor)],
+    &[("&", BinaryOp::And)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+impl Expr {
+    /// Evaluates the expression with 64-bit arithmetic, failing on overflow, division by zero
+    /// or an undefined name
+    pub fn evaluate(&self, symbols: &SymbolTable) -> Result<i64, AssemblerErrorKind> {
+        let overflow = || AssemblerErrorKind::ExpressionOverflow;
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Constant(name) => symbols
+                .constant_value(name)
+                .ok_or_else(|| AssemblerErrorKind::UnknownConstant { name: name.clone() }),
+            Expr::Label(name) => symbols.label(name).map(|symbol| symbol.offset as i64),
+            Expr::Neg(operand) => operand
+                .evaluate(symbols)?
+                .checked_neg()
+                .ok_or_else(overflow),
+            Expr::Not(operand) => Ok(!operand.evaluate(symbols)?),
+            Expr::Binary(op, lhs, rhs) => {
+                let (a, b) = (lhs.evaluate(symbols)?, rhs.evaluate(symbols)?);
+                if b == 0 && matches!(op, BinaryOp::Div | BinaryOp::Rem) {
+                    return Err(AssemblerErrorKind::DivideByZero);
+                }
+                // Offsets in different sections do not share an origin, so their difference
+                // means nothing
+                if let (BinaryOp::Sub, Some(lhs), Some(rhs)) = (op, lhs.label(), rhs.label()) {
+                    if symbols.label(lhs)?.section != symbols.label(rhs)?.section {
+                        return Err(AssemblerErrorKind::LabelSectionMismatch {
+                            lhs: lhs.to_string(),
+                            rhs: rhs.to_string(),
+                        });
+                    }
+                }
+                let shift = || u32::try_from(b).ok().filter(|amount| *amount < 64);
+                let result = match op {
+                    BinaryOp::Or => Some(a | b),
+                    BinaryOp::Xor => Some(a ^ b),
+                    BinaryOp::And => Some(a & b),
+                    BinaryOp::Shl => shift().and_then(|amount| {
+                        // Shifting must not lose significant bits
+                        let result = a << amount;
+                        (result >> amount == a).then_some(result)
+                    }),
+                    BinaryOp::Shr => shift().map(|amount| a >> amount),
+                    BinaryOp::Add => a.checked_add(b),
+                    BinaryOp::Sub => a.checked_sub(b),
+                    BinaryOp::Mul => a.checked_mul(b),
+                    BinaryOp::Div => a.checked_div(b),
+                    BinaryOp::Rem => a.checked_rem(b),
+                };
+                result.ok_or_else(overflow)
+            }
+        }
+    }
+
+    /// Returns `true` if the expression refers to any label
+    pub fn uses_labels(&self) -> bool {
+        self.label().is_some()
+    }
+
+    /// Returns the first label the expression refers to, if any
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Expr::Number(_) | Expr::Constant(_) => None,
+            Expr::Label(name) => Some(name),
+            Expr::Neg(operand) | Expr::Not(operand) => operand.label(),
+            Expr::Binary(_, lhs, rhs) => lhs.label().or_else(|| rhs.label()),
+        }
+    }
+}
+
+/// Parses an expression made of literals, `.equ` names, `@label`s, parentheses, unary `-` and
+/// `~`, and the binary operators `| ^ & << >> + - * / %` with C precedence
+pub fn expression(input: &str) -> IResult<&str, Expr> {
+    binary(0, input)
+}
+
+fn binary(level: usize, input: &str) -> IResult<&str, Expr> {
+    if level == PRECEDENCE.len() {
+        return unary(input);
+    }
+    let (mut input, mut lhs) = binary(level + 1, input)?;
+    loop {
+        let (rest, _) = space0(input)?;
+        let op = PRECEDENCE[level]
+            .iter()
+            .find(|(symbol, _)| rest.starts_with(symbol));
+        let Some((symbol, op)) = op else {
+            return Ok((input, lhs));
+        };
+        let (rest, rhs) = binary(level + 1, &rest[symbol.len()..])?;
+        lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+        input = rest;
+    }
+}
+
+fn unary(input: &str) -> IResult<&str, Expr> {
+    preceded(
+        space0,
+        alt((
+            map(preceded(char('-'), unary), |e| Expr::Neg(Box::new(e))),
+            map(preceded(char('~'), unary), |e| Expr::Not(Box::new(e))),
+            primary,
+        )),
+    )(input)
+}
+
+fn primary(input: &str) -> IResult<&str, Expr> {
+    alt((
+        map(number, Expr::Number),
+        map(label_usage, |token| match token {
+            Token::LabelUsage { name } => Expr::Label(name),
+            _ => unreachable!("label_usage only returns label usages"),
+        }),
+        map(label_name, |name| Expr::Constant(name.to_string())),
+        delimited(char('('), expression, preceded(space0, tag(")"))),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::symbols::{Section, Symbol};
+
+    fn symbols() -> SymbolTable {
+        let mut symbols = SymbolTable::new();
+        symbols.add_constant("BUF_SIZE".to_string(), 64);
+        for (name, offset) in [("start", 8), ("end", 20)] {
+            symbols.add_symbol(Symbol {
+                name: name.to_string(),
+                offset,
+                section: Section::Code,
+            });
+        }
+        symbols
+    }
+
+    fn evaluate(source: &str) -> Result<i64, AssemblerErrorKind> {
+        let (rest, expr) = expression(source).unwrap();
+        assert_eq!(rest, "", "{} was not fully parsed", source);
+        expr.evaluate(&symbols())
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(evaluate("BUF_SIZE * 4 + 1"), Ok(257));
+        assert_eq!(evaluate("1 + 2 * 3 - 4"), Ok(3));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9));
+        assert_eq!(evaluate("1 << 4 | 1"), Ok(17));
+        assert_eq!(evaluate("0xff & ~0x0f ^ 1"), Ok(0xf1));
+        assert_eq!(evaluate("-7 / 2"), Ok(-3));
+        assert_eq!(evaluate("-7 % 2"), Ok(-1));
+        assert_eq!(evaluate("- -'a'"), Ok(97));
+        assert_eq!(evaluate("@end - @start"), Ok(12));
+        assert_eq!(evaluate("(@end-@start)/4"), Ok(3));
+    }
+
+    #[test]
+    fn test_evaluation_errors() {
+        assert_eq!(
+            evaluate("0x7fff_ffff_ffff_ffff + 1"),
+            Err(AssemblerErrorKind::ExpressionOverflow)
+        );
+        assert_eq!(
+            evaluate("1 << 63"),
+            Err(AssemblerErrorKind::ExpressionOverflow)
+        );
+        assert_eq!(
+            evaluate("1 << 64"),
+            Err(AssemblerErrorKind::ExpressionOverflow)
+        );
+        assert_eq!(
+            evaluate("1 >> -1"),
+            Err(AssemblerErrorKind::ExpressionOverflow)
+        );
+        assert_eq!(
+            evaluate("BUF_SIZE % 0"),
+            Err(AssemblerErrorKind::DivideByZero)
+        );
+        assert_eq!(
+            evaluate("MISSING + 1"),
+            Err(AssemblerErrorKind::UnknownConstant {
+                name: "MISSING".to_string()
+            })
+        );
+        assert_eq!(
+            evaluate("@nowhere"),
+            Err(AssemblerErrorKind::UnknownLabel {
+                name: "nowhere".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_uses_labels() {
+        let (_, expr) = expression("BUF_SIZE * (2 + @end)").unwrap();
+        assert!(expr.uses_labels());
+        let (_, expr) = expression("BUF_SIZE * 2").unwrap();
+        assert!(!expr.uses_labels());
+    }
+}
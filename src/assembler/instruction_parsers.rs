@@ -1,118 +1,278 @@
+use crate::assembler::assembler_errors::{AssemblerErrorKind, StatementError};
+use crate::assembler::label_parsers::{label_declaration, label_usage};
+use crate::assembler::opcode::{check_range, operand_schema, OperandKind};
 use crate::assembler::opcode_parsers::*;
-use crate::assembler::operand_parsers::integer_operand;
-use crate::assembler::register_parsers::register;
+use crate::assembler::operand_parsers::{integer_operand, located};
+use crate::assembler::register_parsers::{float_register, register};
+use crate::assembler::symbols::SymbolTable;
 use crate::assembler::Token;
+use crate::instruction::Opcode;
+use crate::vm::INSTRUCTION_SIZE;
 
 use nom::{
     branch::alt,
-    character::complete::{multispace1, space1},
-    combinator::opt,
-    sequence::tuple,
+    character::complete::{char, space0, space1},
+    combinator::{not, opt, verify},
+    multi::many_m_n,
+    sequence::{preceded, terminated, tuple},
     IResult,
 };
 #[derive(Debug, PartialEq)]
 pub struct AssemblerInstruction {
-    opcode: Token,
+    label: Option<Token>,
+    opcode: Option<Token>,
     operand1: Option<Token>,
     operand2: Option<Token>,
     operand3: Option<Token>,
+    /// For each operand, the length of the line from the operand's start
+    operand_positions: Vec<usize>,
 }
 
+/// Parses an optional label declaration followed by an opcode with up to three operands. A
+/// label may also stand on its own, marking the address of whatever follows it. Surrounding
+/// whitespace and line endings are left to the line grammar in `program_parsers`.
 pub fn instruction(input: &str) -> IResult<&str, AssemblerInstruction> {
-    alt((instruction_one, instruction_two))(input)
-}
+    let (input, (label, body)) = verify(
+        tuple((
+            opt(terminated(label_declaration, space0)),
+            opt(tuple((
+                // An opcode never ends in ':', which keeps the next label from parsing as one
+                terminated(opcode, not(char(':'))),
+                many_m_n(0, 3, preceded(space1, located(operand))),
+            ))),
+        )),
+        |(label, body)| label.is_some() || body.is_some(),
+    )(input)?;
+    let (o, operands) = match body {
+        Some((o, operands)) => (Some(o), operands),
+        None => (None, vec![]),
+    };
+    let (operands, operand_positions): (Vec<Token>, Vec<usize>) = operands.into_iter().unzip();
+    let mut operands = operands.into_iter();
 
-fn instruction_one(input: &str) -> IResult<&str, AssemblerInstruction> {
-    let (input, (o, _, r, _, i)) =
-        tuple((opcode, space1, register, space1, integer_operand))(input)?;
     Ok((
         input,
         AssemblerInstruction {
+            label,
             opcode: o,
-            operand1: Some(r),
-            operand2: Some(i),
-            operand3: None,
+            operand1: operands.next(),
+            operand2: operands.next(),
+            operand3: operands.next(),
+            operand_positions,
         },
     ))
 }
 
-fn instruction_two(input: &str) -> IResult<&str, AssemblerInstruction> {
-    let (input, (o, _)) = tuple((opcode, opt(multispace1)))(input)?;
+fn operand(input: &str) -> IResult<&str, Token> {
+    alt((register, float_register, integer_operand, label_usage))(input)
+}
 
-    Ok((
-        input,
-        AssemblerInstruction {
-            opcode: o,
-            operand1: None,
-            operand2: None,
-            operand3: None,
-        },
-    ))
+/// Returns the label an operand refers to, if any
+fn operand_label(operand: &Token) -> Option<&str> {
+    match operand {
+        Token::LabelUsage { name } => Some(name),
+        Token::Expression { expr } => expr.label(),
+        _ => None,
+    }
 }
 
 impl AssemblerInstruction {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut results = vec![];
+    /// Returns the name of the label declared on this instruction, if any
+    pub fn label_name(&self) -> Option<&str> {
+        match &self.label {
+            Some(Token::LabelDeclaration { name }) => Some(name),
+            _ => None,
+        }
+    }
 
-        match &self.opcode {
-            Token::Op { code } => match code {
-                _ => {
-                    results.push(*code as u8);
-                }
-            },
-            _ => {
-                print!("Non-opcode found in opcode field");
-                std::process::exit(1);
+    /// Returns `false` for a label standing on its own
+    pub fn has_opcode(&self) -> bool {
+        self.opcode.is_some()
+    }
+
+    /// Returns, for each operand, the length of the line from the operand's start
+    pub fn operand_positions(&self) -> &[usize] {
+        &self.operand_positions
+    }
+
+    /// Number of bytes this instruction assembles to. This can depend on the value of a
+    /// constant, which must already be in `symbols`.
+    pub fn size(&self, symbols: &SymbolTable) -> Result<usize, StatementError> {
+        Ok(match (&self.opcode, self.expand_load(symbols)?) {
+            (None, _) => 0,
+            (Some(_), Some(expanded)) => expanded.len() * INSTRUCTION_SIZE,
+            (Some(_), None) => INSTRUCTION_SIZE,
+        })
+    }
+
+    pub fn to_bytes(&self, symbols: &SymbolTable) -> Result<Vec<u8>, StatementError> {
+        if let Some(expanded) = self.expand_load(symbols)? {
+            let mut results = vec![];
+            for instruction in &expanded {
+                results.append(&mut instruction.to_bytes(symbols)?);
             }
+            return Ok(results);
+        }
+
+        let code = match &self.opcode {
+            Some(Token::Op { code }) => *code,
+            None => return Ok(vec![]),
+            Some(_) => return Err(AssemblerErrorKind::ExpectedOpcode.into()),
         };
+        if code == Opcode::IGL {
+            return Err(AssemblerErrorKind::UnknownOpcode.into());
+        }
+
+        let schema = operand_schema(code);
+        let operands: Vec<&Token> = [&self.operand1, &self.operand2, &self.operand3]
+            .into_iter()
+            .flatten()
+            .collect();
+        if operands.len() != schema.len() {
+            return Err(AssemblerErrorKind::IncorrectOperandCount {
+                opcode: code,
+                expected: schema.len(),
+                found: operands.len(),
+            }
+            .into());
+        }
 
-        for operand in &[&self.operand1, &self.operand2, &self.operand3] {
-            match operand {
-                Some(t) => AssemblerInstruction::extract_operand(t, &mut results),
-                None => {}
+        if code == Opcode::CALL {
+            if let Some(name) = operand_label(operands[0]) {
+                symbols
+                    .code_label(name)
+                    .map_err(StatementError::in_operand(0))?;
             }
         }
 
-        results
+        let mut results = vec![code as u8];
+        for (position, (kind, t)) in schema.iter().zip(operands).enumerate() {
+            let in_operand = StatementError::in_operand(position);
+            if !AssemblerInstruction::extract_operand(*kind, t, &mut results, symbols)
+                .map_err(&in_operand)?
+            {
+                return Err(in_operand(AssemblerErrorKind::OperandMismatch {
+                    opcode: code,
+                    position: position + 1,
+                    expected: *kind,
+                }));
+            }
+        }
+        results.resize(INSTRUCTION_SIZE, 0);
+
+        Ok(results)
+    }
+
+    /// LOAD only has room for an unsigned 16-bit value, so `load` of any other 32-bit constant
+    /// is a pseudo-instruction that expands to LUI and ORI. An expression that refers to a
+    /// label never expands, because label offsets are not known until the instruction's own
+    /// size is.
+    fn expand_load(
+        &self,
+        symbols: &SymbolTable,
+    ) -> Result<Option<[AssemblerInstruction; 2]>, StatementError> {
+        let (reg_num, value) = match (&self.opcode, &self.operand1, &self.operand2) {
+            (
+                Some(Token::Op { code: Opcode::LOAD }),
+                Some(Token::Register { reg_num }),
+                Some(Token::IntegerOperand { value }),
+            ) => (*reg_num, *value),
+            (
+                Some(Token::Op { code: Opcode::LOAD }),
+                Some(Token::Register { reg_num }),
+                Some(Token::Expression { expr }),
+            ) if !expr.uses_labels() => (
+                *reg_num,
+                expr.evaluate(symbols)
+                    .map_err(StatementError::in_operand(1))?,
+            ),
+            _ => return Ok(None),
+        };
+        if u16::try_from(value).is_ok() || !(i32::MIN as i64..=u32::MAX as i64).contains(&value) {
+            return Ok(None);
+        }
+
+        let half = |code, value| AssemblerInstruction {
+            label: None,
+            opcode: Some(Token::Op { code }),
+            operand1: Some(Token::Register { reg_num }),
+            operand2: Some(Token::IntegerOperand { value }),
+            operand3: None,
+            operand_positions: self.operand_positions.clone(),
+        };
+        let value = value as u32;
+        Ok(Some([
+            half(Opcode::LUI, (value >> 16) as i64),
+            half(Opcode::ORI, (value & 0xffff) as i64),
+        ]))
     }
 
-    fn extract_operand(t: &Token, results: &mut Vec<u8>) {
-        match t {
-            Token::Register { reg_num } => {
+    /// Encodes `t` as an operand of shape `kind`. Returns `false` if the token does not have
+    /// that shape.
+    fn extract_operand(
+        kind: OperandKind,
+        t: &Token,
+        results: &mut Vec<u8>,
+        symbols: &SymbolTable,
+    ) -> Result<bool, AssemblerErrorKind> {
+        match (kind, t) {
+            (_, Token::UnknownRegister { name }) => {
+                return Err(AssemblerErrorKind::UnknownRegister { name: name.clone() });
+            }
+            (OperandKind::Register, Token::Register { reg_num })
+            | (OperandKind::FloatRegister, Token::FloatRegister { reg_num }) => {
                 results.push(*reg_num);
             }
-            Token::IntegerOperand { value } => {
-                let converted = *value as u16;
-                let byte1 = converted;
-                let byte2 = converted >> 8;
-                results.push(byte2 as u8);
-                results.push(byte1 as u8);
+            (OperandKind::Imm8, Token::IntegerOperand { value }) => {
+                let value = check_range(*value, kind.range().unwrap())?;
+                results.push(value as u8);
+            }
+            (OperandKind::Imm16 | OperandKind::SImm16, Token::IntegerOperand { value }) => {
+                let value = check_range(*value, kind.range().unwrap())?;
+                results.extend_from_slice(&(value as u16).to_be_bytes());
+            }
+            (
+                OperandKind::Imm8 | OperandKind::Imm16 | OperandKind::SImm16,
+                Token::Expression { expr },
+            ) => {
+                let value = Token::IntegerOperand {
+                    value: expr.evaluate(symbols)?,
+                };
+                return AssemblerInstruction::extract_operand(kind, &value, results, symbols);
             }
-            _ => {
-                println!("Opcode found in operand field");
-                std::process::exit(1);
+            (
+                OperandKind::Imm8 | OperandKind::Imm16 | OperandKind::SImm16,
+                Token::LabelUsage { name },
+            ) => {
+                let value = Token::IntegerOperand {
+                    value: symbols.label(name)?.offset as i64,
+                };
+                return AssemblerInstruction::extract_operand(kind, &value, results, symbols);
             }
+            _ => return Ok(false),
         }
+        Ok(true)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::instruction::Opcode;
 
     #[test]
     fn test_parse_instruction_form_one() {
-        let result = instruction_one("load $0 #100");
+        let result = instruction("load $0 #100");
         assert_eq!(
             result,
             Ok((
                 "",
                 AssemblerInstruction {
-                    opcode: Token::Op { code: Opcode::LOAD },
+                    label: None,
+                    opcode: Some(Token::Op { code: Opcode::LOAD }),
                     operand1: Some(Token::Register { reg_num: 0 }),
                     operand2: Some(Token::IntegerOperand { value: 100 }),
-                    operand3: None
+                    operand3: None,
+                    operand_positions: vec![7, 4],
                 }
             ))
         );
@@ -120,16 +280,277 @@ mod tests {
 
     #[test]
     fn test_parse_instruction_form_two() {
-        let result = instruction_two("hlt");
+        let result = instruction("hlt");
         assert_eq!(
             result,
             Ok((
                 "",
                 AssemblerInstruction {
-                    opcode: Token::Op { code: Opcode::HLT },
+                    label: None,
+                    opcode: Some(Token::Op { code: Opcode::HLT }),
                     operand1: None,
                     operand2: None,
-                    operand3: None
+                    operand3: None,
+                    operand_positions: vec![],
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_instruction_form_three() {
+        let result = instruction("loadb $0 $1 #4\n");
+        assert_eq!(
+            result,
+            Ok((
+                "\n",
+                AssemblerInstruction {
+                    label: None,
+                    opcode: Some(Token::Op {
+                        code: Opcode::LOADB
+                    }),
+                    operand1: Some(Token::Register { reg_num: 0 }),
+                    operand2: Some(Token::Register { reg_num: 1 }),
+                    operand3: Some(Token::IntegerOperand { value: 4 }),
+                    operand_positions: vec![9, 6, 3],
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_instruction_to_bytes_pads() {
+        let (_, parsed) = instruction("storew $2 $1 #8").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&SymbolTable::new()).unwrap(),
+            vec![Opcode::STOREW as u8, 2, 1, 8]
+        );
+        let (_, parsed) = instruction("jmp $3").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&SymbolTable::new()).unwrap(),
+            vec![Opcode::JMP as u8, 3, 0, 0]
+        );
+        let (_, parsed) = instruction("itof $f1 $2").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&SymbolTable::new()).unwrap(),
+            vec![Opcode::ITOF as u8, 1, 2, 0]
+        );
+        let (_, parsed) = instruction("shli $1 $2 #5").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&SymbolTable::new()).unwrap(),
+            vec![Opcode::SHLI as u8, 1, 2, 5]
+        );
+        let (_, parsed) = instruction("hlt").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&SymbolTable::new()).unwrap(),
+            vec![Opcode::HLT as u8, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_wide_load_expands() {
+        let (_, parsed) = instruction("load $3 #-123456").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&SymbolTable::new()).unwrap(),
+            vec![
+                Opcode::LUI as u8,
+                3,
+                0xff,
+                0xfe,
+                Opcode::ORI as u8,
+                3,
+                0x1d,
+                0xc0
+            ]
+        );
+        let (_, parsed) = instruction("load $3 #65535").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&SymbolTable::new()).unwrap(),
+            vec![Opcode::LOAD as u8, 3, 0xff, 0xff]
+        );
+        let (_, parsed) = instruction("load $3 #65536").unwrap();
+        assert_eq!(parsed.to_bytes(&SymbolTable::new()).unwrap().len(), 8);
+    }
+
+    #[test]
+    fn test_parse_instruction_with_label() {
+        let (rest, parsed) = instruction("loop: jmp @loop\nhlt").unwrap();
+        assert_eq!(rest, "\nhlt");
+        assert_eq!(parsed.label_name(), Some("loop"));
+        assert_eq!(
+            parsed.operand1,
+            Some(Token::LabelUsage {
+                name: "loop".to_string()
+            })
+        );
+
+        // A label on its own line does not swallow the next line
+        let (rest, parsed) = instruction("start:\nend: hlt").unwrap();
+        assert_eq!(rest, "\nend: hlt");
+        assert_eq!(parsed.label_name(), Some("start"));
+        assert_eq!(parsed.opcode, None);
+        assert_eq!(parsed.size(&SymbolTable::new()), Ok(0));
+    }
+
+    #[test]
+    fn test_label_usage_to_bytes() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_symbol(crate::assembler::symbols::Symbol {
+            name: "target".to_string(),
+            offset: 260,
+            section: crate::assembler::symbols::Section::Code,
+        });
+        let (_, parsed) = instruction("load $2 @target").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&symbols).unwrap(),
+            vec![Opcode::LOAD as u8, 2, 1, 4]
+        );
+        let (_, parsed) = instruction("load $2 @missing").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&symbols),
+            Err(StatementError::in_operand(1)(
+                AssemblerErrorKind::UnknownLabel {
+                    name: "missing".to_string()
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_operand_schema_is_enforced() {
+        let symbols = SymbolTable::new();
+        let (_, parsed) = instruction("add $0 $1 $2").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&symbols).unwrap(),
+            vec![Opcode::ADD as u8, 0, 1, 2]
+        );
+        let (_, parsed) = instruction("call #260").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&symbols).unwrap(),
+            vec![Opcode::CALL as u8, 1, 4, 0]
+        );
+
+        let (_, parsed) = instruction("add $0 $1").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&symbols),
+            Err(AssemblerErrorKind::IncorrectOperandCount {
+                opcode: Opcode::ADD,
+                expected: 3,
+                found: 2
+            }
+            .into())
+        );
+        let (_, parsed) = instruction("hlt $0").unwrap();
+        assert!(parsed.to_bytes(&symbols).is_err());
+        let (_, parsed) = instruction("eq $0 #1").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&symbols),
+            Err(StatementError::in_operand(1)(
+                AssemblerErrorKind::OperandMismatch {
+                    opcode: Opcode::EQ,
+                    position: 2,
+                    expected: OperandKind::Register
+                }
+            ))
+        );
+        let (_, parsed) = instruction("addf64 $f0 $1 $f2").unwrap();
+        assert!(parsed.to_bytes(&symbols).is_err());
+        let (_, parsed) = instruction("bogus $0").unwrap();
+        assert_eq!(
+            parsed.to_bytes(&symbols),
+            Err(AssemblerErrorKind::UnknownOpcode.into())
+        );
+    }
+
+    #[test]
+    fn test_immediates_are_range_checked() {
+        let symbols = SymbolTable::new();
+        let bytes = |source| instruction(source).unwrap().1.to_bytes(&symbols);
+        assert_eq!(
+            bytes("addi $1 #-0x8000"),
+            Ok(vec![Opcode::ADDI as u8, 1, 0x80, 0])
+        );
+        assert_eq!(
+            bytes("addi $1 #0x8000"),
+            Err(StatementError::in_operand(1)(
+                AssemblerErrorKind::ValueOutOfRange {
+                    value: 0x8000,
+                    min: -0x8000,
+                    max: 0x7fff
+                }
+            ))
+        );
+        assert_eq!(
+            bytes("loadb $1 $2 #'A'"),
+            Ok(vec![Opcode::LOADB as u8, 1, 2, 65])
+        );
+        assert_eq!(
+            bytes("shli $1 $2 #256"),
+            Err(StatementError::in_operand(2)(
+                AssemblerErrorKind::ValueOutOfRange {
+                    value: 256,
+                    min: 0,
+                    max: 255
+                }
+            ))
+        );
+        assert!(bytes("loadb $1 $2 #-1").is_err());
+        assert!(bytes("ori $1 #0x1_0000").is_err());
+        assert_eq!(bytes("load $1 #0xdead_beef").unwrap().len(), 8);
+        assert!(bytes("load $1 #0x1_0000_0000").is_err());
+
+        let mut symbols = SymbolTable::new();
+        symbols.add_symbol(crate::assembler::symbols::Symbol {
+            name: "far".to_string(),
+            offset: 300,
+            section: crate::assembler::symbols::Section::Code,
+        });
+        let (_, parsed) = instruction("loadb $1 $2 @far").unwrap();
+        assert!(parsed.to_bytes(&symbols).is_err());
+    }
+
+    #[test]
+    fn test_expression_operands_to_bytes() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_constant("BUF_SIZE".to_string(), 64);
+        symbols.add_constant("BIG".to_string(), 0x12345);
+        let bytes = |source| instruction(source).unwrap().1.to_bytes(&symbols);
+        assert_eq!(
+            bytes("addi $1 #(BUF_SIZE * 4 + 1)"),
+            Ok(vec![Opcode::ADDI as u8, 1, 1, 1])
+        );
+        assert_eq!(
+            bytes("subi $1 #(-BUF_SIZE)"),
+            Ok(vec![Opcode::SUBI as u8, 1, 0xff, 0xc0])
+        );
+        assert_eq!(
+            bytes("load $2 #BIG"),
+            Ok(vec![
+                Opcode::LUI as u8,
+                2,
+                0,
+                1,
+                Opcode::ORI as u8,
+                2,
+                0x23,
+                0x45
+            ])
+        );
+        assert_eq!(
+            bytes("shli $1 $1 #(BUF_SIZE * 4)"),
+            Err(StatementError::in_operand(2)(
+                AssemblerErrorKind::ValueOutOfRange {
+                    value: 256,
+                    min: 0,
+                    max: 255
+                }
+            ))
+        );
+        assert_eq!(
+            bytes("addi $1 #UNDEFINED"),
+            Err(StatementError::in_operand(1)(
+                AssemblerErrorKind::UnknownConstant {
+                    name: "UNDEFINED".to_string()
                 }
             ))
         );
@@ -0,0 +1,78 @@
+use crate::assembler::Token;
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{alpha1, alphanumeric1, char},
+    combinator::recognize,
+    multi::many0,
+    sequence::{pair, preceded, terminated},
+    IResult,
+};
+
+/// Parses a label name: a letter or underscore followed by letters, digits or underscores
+pub fn label_name(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0(alt((alphanumeric1, tag("_")))),
+    ))(input)
+}
+
+/// Parses a label declaration such as `loop:`
+pub fn label_declaration(input: &str) -> IResult<&str, Token> {
+    let (input, name) = terminated(label_name, char(':'))(input)?;
+
+    Ok((
+        input,
+        Token::LabelDeclaration {
+            name: name.to_string(),
+        },
+    ))
+}
+
+/// Parses a reference to a label such as `@loop`
+pub fn label_usage(input: &str) -> IResult<&str, Token> {
+    let (input, name) = preceded(char('@'), label_name)(input)?;
+
+    Ok((
+        input,
+        Token::LabelUsage {
+            name: name.to_string(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_label_declaration() {
+        let result = label_declaration("loop_2:");
+        assert_eq!(
+            result,
+            Ok((
+                "",
+                Token::LabelDeclaration {
+                    name: "loop_2".to_string()
+                }
+            ))
+        );
+        assert!(label_declaration("loop").is_err());
+        assert!(label_declaration("2loop:").is_err());
+    }
+
+    #[test]
+    fn test_parse_label_usage() {
+        let result = label_usage("@loop");
+        assert_eq!(
+            result,
+            Ok((
+                "",
+                Token::LabelUsage {
+                    name: "loop".to_string()
+                }
+            ))
+        );
+        assert!(label_usage("loop").is_err());
+    }
+}
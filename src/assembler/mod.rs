@@ -1,15 +1,807 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::assembler::assembler_errors::{
+    AssemblerError, AssemblerErrorKind, SourceLocation, StatementError,
+};
+use crate::assembler::expression::Expr;
+use crate::assembler::program_parsers::{statements, Statement, StatementKind};
+use crate::assembler::symbols::{Section, Symbol, SymbolTable};
+use crate::executable::{
+    Executable, ExecutableSymbol, SectionKind, WriteError, MAX_SYMBOL_NAME_LEN,
+};
 use crate::instruction::Opcode;
 
+pub mod assembler_errors;
+pub mod directive_parsers;
+pub mod disassembler;
+pub mod expression;
 pub mod instruction_parsers;
+pub mod label_parsers;
 pub mod opcode;
 pub mod opcode_parsers;
 pub mod operand_parsers;
+pub mod preprocessor;
 pub mod program_parsers;
 pub mod register_parsers;
+pub mod symbols;
 
 #[derive(Debug, PartialEq)]
 pub enum Token {
-    Op { code: Opcode },
-    Register { reg_num: u8 },
-    IntegerOperand { value: i32 },
+    Op {
+        code: Opcode,
+    },
+    Register {
+        reg_num: u8,
+    },
+    FloatRegister {
+        reg_num: u8,
+    },
+    /// A `$name` that is not a register number, ABI name or alias
+    UnknownRegister {
+        name: String,
+    },
+    IntegerOperand {
+        value: i64,
+    },
+    FloatOperand {
+        value: f64,
+    },
+    LabelDeclaration {
+        name: String,
+    },
+    LabelUsage {
+        name: String,
+    },
+    Directive {
+        name: String,
+    },
+    StringOperand {
+        value: String,
+    },
+    Expression {
+        expr: Expr,
+    },
+    /// A bare name, such as the constant being defined by `.equ`
+    Identifier {
+        name: String,
+    },
+}
+
+/// The output of the assembler. `code` is loaded as the VM's program, `data` as its read-only
+/// data area and `float_constants` as the constant pool LOADF64 reads.
+#[derive(Debug, Default, PartialEq)]
+pub struct AssembledProgram {
+    pub code: Vec<u8>,
+    pub data: Vec<u8>,
+    /// Values declared with `.double`, in declaration order
+    pub float_constants: Vec<f64>,
+    /// Offset into `code` that execution starts at, set with `.entry @label`
+    pub entry: usize,
+    /// Every label in the program, in declaration order
+    pub symbols: Vec<Symbol>,
+}
+
+impl AssembledProgram {
+    /// Encodes the program as an executable file that `VM::load_executable` accepts
+    pub fn to_executable(&self) -> Result<Vec<u8>, WriteError> {
+        let symbols = self
+            .symbols
+            .iter()
+            .map(|symbol| ExecutableSymbol {
+                name: symbol.name.clone(),
+                section: match symbol.section {
+                    Section::Code => SectionKind::Code,
+                    Section::Data => SectionKind::Data,
+                    Section::Constants => SectionKind::Constants,
+                },
+                offset: symbol.offset as u32,
+            })
+            .collect();
+        Executable {
+            entry: self.entry as u32,
+            code: self.code.clone(),
+            data: self.data.clone(),
+            float_constants: self.float_constants.clone(),
+            symbols,
+        }
+        .to_bytes()
+    }
+
+    /// Writes the program to `path` as an executable file. A program that cannot be encoded
+    /// fails with `io::ErrorKind::InvalidData`.
+    pub fn write_executable(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = self
+            .to_executable()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, bytes)
+    }
+}
+
+/// Two-pass assembler: the first pass records the address of every label, the second encodes
+/// the instructions with label references resolved to byte offsets within their section
+#[derive(Debug, Default)]
+pub struct Assembler {
+    /// Labels found by the most recent call to `assemble`
+    pub symbols: SymbolTable,
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler {
+            symbols: SymbolTable::new(),
+        }
+    }
+
+    /// Assembles `raw` into bytecode, or returns every error found, in source order
+    pub fn assemble(&mut self, raw: &str) -> Result<AssembledProgram, Vec<AssemblerError>> {
+        self.assemble_source(raw, "<input>")
+    }
+
+    /// Reads and assembles the file at `path`. `.include` paths in it are resolved relative to
+    /// its directory.
+    pub fn assemble_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<AssembledProgram, Vec<AssemblerError>> {
+        let file = path.as_ref().to_string_lossy().into_owned();
+        match fs::read_to_string(path.as_ref()) {
+            Ok(raw) => self.assemble_source(&raw, &file),
+            Err(e) => {
+                let kind = AssemblerErrorKind::ReadFailed {
+                    path: file.clone(),
+                    error: e.to_string(),
+                };
+                let location = SourceLocation {
+                    file,
+                    line: 0,
+                    column: 0,
+                    snippet: String::new(),
+                    macro_calls: vec![],
+                    sequence: 0,
+                };
+                Err(vec![AssemblerError::new(kind, &location)])
+            }
+        }
+    }
+
+    /// Assembles `raw`, reporting errors as coming from `file`. `.include` paths are resolved
+    /// relative to the directory `file` is in.
+    pub fn assemble_source(
+        &mut self,
+        raw: &str,
+        file: &str,
+    ) -> Result<AssembledProgram, Vec<AssemblerError>> {
+        self.symbols = SymbolTable::new();
+        let (statements, mut errors) = statements(raw, file);
+
+        let (mut symbol_errors, unsizable) = self.extract_symbols(&statements);
+        errors.append(&mut symbol_errors);
+        let mut program = AssembledProgram::default();
+        let mut section = Section::Code;
+        let mut entry_declared = false;
+        for (index, statement) in statements.iter().enumerate() {
+            section = statement.section().unwrap_or(section);
+            if unsizable.contains(&index) {
+                // Already reported by the first pass
+                continue;
+            }
+            if let StatementKind::Directive(directive) = &statement.kind {
+                if let Some(label) = directive.entry_label() {
+                    let entry = if entry_declared {
+                        Err(AssemblerErrorKind::DuplicateEntry)
+                    } else {
+                        self.entry_point(label)
+                    };
+                    match entry {
+                        Ok(entry) => program.entry = entry,
+                        Err(kind) => {
+                            errors.push(statement.error(StatementError::in_operand(0)(kind)))
+                        }
+                    }
+                    entry_declared = true;
+                }
+            }
+            match self.encode(statement, section) {
+                Ok(mut bytes) => match statement.placement(section) {
+                    Section::Code => program.code.append(&mut bytes),
+                    Section::Data => program.data.append(&mut bytes),
+                    Section::Constants => {
+                        program
+                            .float_constants
+                            .extend(bytes.chunks_exact(8).map(|value| {
+                                f64::from_be_bytes(value.try_into().expect("chunks are 8 bytes"))
+                            }))
+                    }
+                },
+                Err(error) => errors.push(statement.error(error)),
+            }
+        }
+
+        if errors.is_empty() {
+            program.symbols = self.symbols.symbols().to_vec();
+            Ok(program)
+        } else {
+            // Each pass reports its own errors, so put them back in the order the lines were
+            // reached, following includes and macro calls. The sort is stable, so errors on the
+            // same line stay in pass order.
+            errors.sort_by_key(|error| error.sequence);
+            Err(errors)
+        }
+    }
+
+    /// First pass: records the section and offset of every label declaration and the value of
+    /// every `.equ` constant. A label on a `.code` or `.data` directive belongs to the section
+    /// it switches to, and a label on `.double` to the constant pool, with its first value's
+    /// index as its offset. Constants are evaluated in source order, so a constant, and any
+    /// expression that decides a statement's size, can only use constants and labels declared
+    /// before it. Also returns the indices of the statements whose size could not be computed.
+    fn extract_symbols(&mut self, statements: &[Statement]) -> (Vec<AssemblerError>, Vec<usize>) {
+        let mut errors = vec![];
+        let mut unsizable = vec![];
+        let mut section = Section::Code;
+        let (mut code_offset, mut data_offset, mut constant_index) = (0, 0, 0);
+        for (index, statement) in statements.iter().enumerate() {
+            section = statement.section().unwrap_or(section);
+            let offset = match statement.placement(section) {
+                Section::Code => &mut code_offset,
+                Section::Data => &mut data_offset,
+                Section::Constants => &mut constant_index,
+            };
+            if let Some(name) = statement.label_name() {
+                if self.symbols.has_symbol(name) {
+                    errors.push(AssemblerError::new(
+                        AssemblerErrorKind::DuplicateLabel {
+                            name: name.to_string(),
+                        },
+                        &statement.location,
+                    ));
+                } else if name.len() > MAX_SYMBOL_NAME_LEN {
+                    errors.push(AssemblerError::new(
+                        AssemblerErrorKind::LabelTooLong {
+                            name: name.to_string(),
+                            max: MAX_SYMBOL_NAME_LEN,
+                        },
+                        &statement.location,
+                    ));
+                } else {
+                    self.symbols.add_symbol(Symbol {
+                        name: name.to_string(),
+                        offset: *offset,
+                        section: statement.placement(section),
+                    });
+                }
+            }
+            if let StatementKind::Directive(directive) = &statement.kind {
+                match directive.constant(&self.symbols) {
+                    Ok(Some((name, _))) if self.symbols.has_constant(name) => {
+                        let kind = AssemblerErrorKind::DuplicateConstant {
+                            name: name.to_string(),
+                        };
+                        errors.push(AssemblerError::new(kind, &statement.location));
+                    }
+                    Ok(Some((name, value))) => self.symbols.add_constant(name.to_string(), value),
+                    Ok(None) => {}
+                    Err(error) => errors.push(statement.error(error)),
+                }
+            }
+            match statement.size(&self.symbols) {
+                Ok(size) => *offset += size,
+                Err(error) => {
+                    errors.push(statement.error(error));
+                    unsizable.push(index);
+                }
+            }
+        }
+        (errors, unsizable)
+    }
+
+    /// Returns the offset of `label`, which must be declared in the code section
+    fn entry_point(&self, label: &str) -> Result<usize, AssemblerErrorKind> {
+        match self.symbols.symbol(label) {
+            Some(symbol) if symbol.section == Section::Code => Ok(symbol.offset),
+            Some(_) => Err(AssemblerErrorKind::InvalidDirectiveOperands {
+                directive: "entry".to_string(),
+                expected: "a label in the code section",
+            }),
+            None => Err(AssemblerErrorKind::UnknownLabel {
+                name: label.to_string(),
+            }),
+        }
+    }
+
+    /// Second pass: encodes a statement, checking that it belongs in `section`
+    fn encode(&self, statement: &Statement, section: Section) -> Result<Vec<u8>, StatementError> {
+        match &statement.kind {
+            StatementKind::Instruction(instruction) => {
+                if section == Section::Data && instruction.has_opcode() {
+                    return Err(AssemblerErrorKind::InstructionOutsideCode.into());
+                }
+                instruction.to_bytes(&self.symbols)
+            }
+            StatementKind::Directive(directive) => {
+                let bytes = directive.to_bytes(&self.symbols)?;
+                if section == Section::Code && directive.is_data() {
+                    return Err(AssemblerErrorKind::DataOutsideData {
+                        directive: directive.name().to_string(),
+                    }
+                    .into());
+                }
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::VM;
+
+    #[test]
+    fn test_assemble_program_with_labels() {
+        let mut asm = Assembler::new();
+        let source = "load $0 #3\nload $1 #1\nload $2 @loop\nloop: sub $0 $1 $0\njnz $2\nhlt\nend:";
+        let bytecode = asm.assemble(source).unwrap().code;
+        assert_eq!(asm.symbols.symbol_value("loop"), Some(12));
+        assert_eq!(asm.symbols.symbol_value("end"), Some(24));
+        assert_eq!(bytecode.len(), 24);
+        assert_eq!(&bytecode[8..12], &[Opcode::LOAD as u8, 2, 0, 12]);
+
+        let mut vm = VM::new();
+        vm.program = bytecode;
+        vm.run().unwrap();
+        assert_eq!(vm.registers[0], 0);
+    }
+
+    #[test]
+    fn test_forward_label_reference() {
+        let mut asm = Assembler::new();
+        let bytecode = asm
+            .assemble("load $0 @done\njmp $0\nhlt\ndone: hlt")
+            .unwrap()
+            .code;
+        assert_eq!(&bytecode[0..4], &[Opcode::LOAD as u8, 0, 0, 12]);
+    }
+
+    #[test]
+    fn test_label_errors() {
+        let mut asm = Assembler::new();
+        let errors = asm
+            .assemble("a: load $0 @nowhere\na: hlt\nload $1 @elsewhere")
+            .unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                AssemblerErrorKind::UnknownLabel {
+                    name: "nowhere".to_string()
+                },
+                AssemblerErrorKind::DuplicateLabel {
+                    name: "a".to_string()
+                },
+                AssemblerErrorKind::UnknownLabel {
+                    name: "elsewhere".to_string()
+                },
+            ]
+        );
+        let lines: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_program_to_bytes() {
+        let mut asm = Assembler::new();
+        let program = asm.assemble("load $0 #100").unwrap();
+        assert_eq!(program.code, vec![Opcode::LOAD as u8, 0, 0, 100]);
+    }
+
+    #[test]
+    fn test_assemble_multi_line_program() {
+        let mut asm = Assembler::new();
+        let program = asm
+            .assemble("load $1 #16\naloc $1\nstoreb $1 $0 #3\nhlt\n")
+            .unwrap();
+        assert_eq!(program.code.len(), 16);
+        assert_eq!(&program.code[..4], &[Opcode::LOAD as u8, 1, 0, 16]);
+    }
+
+    #[test]
+    fn test_wide_load_runs() {
+        let mut asm = Assembler::new();
+        let mut vm = VM::new();
+        vm.program = asm
+            .assemble("load $0 #-123456\naddi $0 #-4\n")
+            .unwrap()
+            .code;
+        vm.run().unwrap();
+        assert_eq!(vm.registers[0], -123460);
+    }
+
+    #[test]
+    fn test_collects_errors_from_every_line() {
+        let mut asm = Assembler::new();
+        let errors = asm
+            .assemble_source("load $0\nadd $0 $1 $2\n?\nfoo $1\njmp @x\n", "bad.iasm")
+            .unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![1, 3, 4, 5]);
+        assert!(errors.iter().all(|e| e.file == "bad.iasm"));
+        assert_eq!(errors[1].snippet, "?");
+    }
+
+    #[test]
+    fn test_operand_errors_point_at_operand() {
+        let mut asm = Assembler::new();
+        let source = "\
+load $0 #99999999999
+  add $0 $1 #2
+.data
+.byte #1, #300
+.space @nowhere
+.code
+.entry @missing";
+        let errors = asm.assemble_source(source, "ops.iasm").unwrap_err();
+        let positions: Vec<_> = errors.iter().map(|e| (e.line, e.column)).collect();
+        assert_eq!(positions, vec![(1, 9), (2, 13), (4, 11), (5, 8), (7, 8)]);
+        assert!(errors[0].to_string().ends_with(
+            "\
+1 | load $0 #99999999999
+  |         ^"
+        ));
+    }
+
+    #[test]
+    fn test_data_section() {
+        let mut asm = Assembler::new();
+        let source = "\
+.data
+greeting: .asciiz \"hi\"
+table: .half #7, #9
+ptrs: .word @greeting @table
+.code
+load $0 @table
+dloadh $1 $0 #2
+hlt";
+        let program = asm.assemble(source).unwrap();
+        assert_eq!(asm.symbols.symbol_value("table"), Some(3));
+        assert_eq!(
+            program.data,
+            vec![b'h', b'i', 0, 0, 7, 0, 9, 0, 0, 0, 0, 0, 0, 0, 3]
+        );
+        assert_eq!(program.code.len(), 12);
+
+        let mut vm = VM::new();
+        vm.program = program.code;
+        vm.ro_data = program.data;
+        vm.run().unwrap();
+        assert_eq!(vm.registers[1], 9);
+    }
+
+    #[test]
+    fn test_section_errors() {
+        let mut asm = Assembler::new();
+        let errors = asm
+            .assemble(".byte #1\n.data\nhlt\nok:\n.frob #1\n.code #2")
+            .unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                AssemblerErrorKind::DataOutsideData {
+                    directive: "byte".to_string()
+                },
+                AssemblerErrorKind::InstructionOutsideCode,
+                AssemblerErrorKind::UnknownDirective {
+                    name: "frob".to_string()
+                },
+                AssemblerErrorKind::InvalidDirectiveOperands {
+                    directive: "code".to_string(),
+                    expected: "no operands"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_float_constants() {
+        let mut asm = Assembler::new();
+        let source = "\
+.data
+pi: .double #3.25
+.code
+loadf64 $f0 @pi
+loadf64 $f1 @half
+addf64 $f0 $f1 $f2
+hlt
+half: .double #0.5, #2
+call @half";
+        let errors = asm.assemble(source).unwrap_err();
+        assert_eq!(
+            errors[0].kind,
+            AssemblerErrorKind::DataLabelAsCodeAddress {
+                name: "half".to_string()
+            }
+        );
+
+        let program = asm.assemble(source.trim_end_matches("call @half")).unwrap();
+        assert_eq!(program.float_constants, vec![3.25, 0.5, 2.0]);
+        assert_eq!(asm.symbols.symbol_value("half"), Some(1));
+        assert_eq!(
+            asm.symbols.symbol("pi").unwrap().section,
+            Section::Constants
+        );
+        assert!(program.data.is_empty());
+        assert_eq!(program.code.len(), 16);
+
+        let mut vm = VM::new();
+        vm.load_executable(&program.to_executable().unwrap())
+            .unwrap();
+        assert_eq!(vm.float_constants, program.float_constants);
+        vm.run().unwrap();
+        assert_eq!(vm.float_registers[2], 3.75);
+    }
+
+    #[test]
+    fn test_label_section_errors() {
+        let mut asm = Assembler::new();
+        let source = "\
+start: nop
+.data
+msg: .asciiz \"hi\"
+.equ SIZE #(@msg - @start)
+.code
+call @msg
+call #(@msg + 4)
+load $0 @msg
+call @done
+done: hlt";
+        let errors = asm.assemble(source).unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| (e.line, e.kind.clone())).collect();
+        let data_label = AssemblerErrorKind::DataLabelAsCodeAddress {
+            name: "msg".to_string(),
+        };
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    4,
+                    AssemblerErrorKind::LabelSectionMismatch {
+                        lhs: "msg".to_string(),
+                        rhs: "start".to_string()
+                    }
+                ),
+                (6, data_label.clone()),
+                (7, data_label),
+            ]
+        );
+        assert_eq!(
+            errors[0].kind.to_string(),
+            "cannot subtract @start from @msg because they are in different sections"
+        );
+    }
+
+    #[test]
+    fn test_macro_errors_point_at_body_and_call_site() {
+        let mut asm = Assembler::new();
+        let source = "\
+.macro jump_to target
+    load $0 @%target
+    jmp $0
+.endm
+jump_to end
+end: hlt
+jump_to nowhere";
+        let errors = asm.assemble_source(source, "m.iasm").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "\
+m.iasm:2:13: error: undefined label @nowhere
+2 |     load $0 @nowhere
+  |             ^
+m.iasm:7:1: note: in expansion of macro jump_to
+7 | jump_to nowhere
+  | ^"
+        );
+
+        let program = asm
+            .assemble(&source.replace("jump_to nowhere", ""))
+            .unwrap();
+        assert_eq!(&program.code[0..4], &[Opcode::LOAD as u8, 0, 0, 8]);
+
+        // An error in a body is ordered by its call site, not by the body's line
+        let errors = asm
+            .assemble(".macro broken\n    frob $0\n.endm\nnop $1\nbroken")
+            .unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![4, 2]);
+    }
+
+    #[test]
+    fn test_include_files() {
+        let dir = std::env::temp_dir().join(format!("iridium-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("main.iasm"),
+            ".include \"lib/util.iasm\"\n.include \"lib/util.iasm\"\nstart: twice $1\nhlt\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("lib/util.iasm"),
+            ".include \"consts.iasm\"\n.macro twice reg\n    add %reg %reg %reg\n.endm\n",
+        )
+        .unwrap();
+        fs::write(dir.join("lib/consts.iasm"), "; nothing but a comment\n").unwrap();
+        fs::write(dir.join("lib/a.iasm"), "hlt\n.include \"b.iasm\"\n").unwrap();
+        fs::write(dir.join("lib/b.iasm"), "nop\n.include \"a.iasm\"\n").unwrap();
+        fs::write(
+            dir.join("bad.iasm"),
+            ".include \"lib/a.iasm\"\n.include \"missing.iasm\"\n",
+        )
+        .unwrap();
+
+        let mut asm = Assembler::new();
+        let program = asm.assemble_file(dir.join("main.iasm")).unwrap();
+        assert_eq!(
+            program.code,
+            vec![Opcode::ADD as u8, 1, 1, 1, Opcode::HLT as u8, 0, 0, 0]
+        );
+
+        let errors = asm.assemble_file(dir.join("bad.iasm")).unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| &e.kind).collect();
+        // Errors come in include order: the cycle is found while including a.iasm on line 1
+        assert!(matches!(kinds[0], AssemblerErrorKind::IncludeCycle { .. }));
+        assert!(errors[0].file.ends_with("b.iasm"));
+        assert_eq!(errors[0].line, 2);
+        assert!(matches!(kinds[1], AssemblerErrorKind::ReadFailed { .. }));
+        assert!(errors[1].file.ends_with("bad.iasm"));
+        assert_eq!(errors[1].line, 2);
+
+        let errors = asm.assemble_file(dir.join("nope.iasm")).unwrap_err();
+        assert_eq!(errors[0].line, 0);
+        assert!(errors[0]
+            .to_string()
+            .contains("nope.iasm: error: cannot read"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_constants_and_expressions() {
+        let mut asm = Assembler::new();
+        let source = "\
+.equ BUF_SIZE #16
+.equ BIG #(BUF_SIZE << 16)
+.data
+table: .word #1 #2 #3
+table_end:
+.equ TABLE_LEN #((@table_end - @table) / 4)
+buffer: .space #(BUF_SIZE * 2)
+.code
+load $0 #BIG
+load $1 #TABLE_LEN
+load $2 #(@done - @start)
+start: addi $1 #(-BUF_SIZE + 1)
+done: hlt";
+        let program = asm.assemble(source).unwrap();
+        assert_eq!(asm.symbols.constant_value("TABLE_LEN"), Some(3));
+        assert_eq!(program.data.len(), 12 + 32);
+        assert_eq!(asm.symbols.symbol_value("start"), Some(16));
+
+        let mut vm = VM::new();
+        vm.program = program.code;
+        vm.run().unwrap();
+        assert_eq!(vm.registers[0], 16 << 16);
+        assert_eq!(vm.registers[1], -12);
+        assert_eq!(vm.registers[2], 4);
+    }
+
+    #[test]
+    fn test_constant_errors() {
+        let mut asm = Assembler::new();
+        let errors = asm
+            .assemble(".equ A #1\n.equ A #2\n.equ B #(LATER + 1)\n.equ LATER #1\nload $0 #(A / 0)\n.data\n.space #(@fwd)\nfwd:")
+            .unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                AssemblerErrorKind::DuplicateConstant {
+                    name: "A".to_string()
+                },
+                AssemblerErrorKind::UnknownConstant {
+                    name: "LATER".to_string()
+                },
+                AssemblerErrorKind::DivideByZero,
+                AssemblerErrorKind::UnknownLabel {
+                    name: "fwd".to_string()
+                },
+            ]
+        );
+        let lines: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 3, 5, 7]);
+    }
+
+    #[test]
+    fn test_register_names() {
+        let mut asm = Assembler::new();
+        let program = asm
+            .assemble(".alias total $v0\nload $a0 #2\nadd $a0 $a0 $total\nhlt")
+            .unwrap();
+        assert_eq!(&program.code[4..8], &[Opcode::ADD as u8, 2, 2, 0]);
+
+        let errors = asm
+            .assemble("load $32 #1\nadd $1 $total $2\nloadf64 $f32 #0")
+            .unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.message()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "unknown register $32",
+                "unknown register $total",
+                "unknown register $f32"
+            ]
+        );
+
+        // Errors show the line as written, not with its aliases replaced
+        let errors = asm
+            .assemble(".alias counter $3\nadd $counter $counter #1")
+            .unwrap_err();
+        assert_eq!(errors[0].snippet, "add $counter $counter #1");
+    }
+
+    #[test]
+    fn test_executable_round_trip() {
+        let source = "\
+.data
+greeting: .asciiz \"hi\"
+.code
+.entry @main
+helper: load $v0 #7
+ret
+main: load $a0 #0
+dloadb $v1 $a0 #1
+call @helper
+hlt";
+        let mut asm = Assembler::new();
+        let program = asm.assemble(source).unwrap();
+        assert_eq!(program.entry, 8);
+        assert_eq!(program.symbols.len(), 3);
+
+        let path = std::env::temp_dir().join(format!("iridium-exe-{}.irx", std::process::id()));
+        program.write_executable(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let executable = Executable::from_bytes(&bytes).unwrap();
+        assert_eq!(executable.symbols[2].name, "main");
+        assert_eq!(executable.symbols[0].section, SectionKind::Data);
+
+        let mut vm = VM::new();
+        vm.load_executable(&bytes).unwrap();
+        assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+        assert_eq!(vm.registers[0], 7);
+        assert_eq!(vm.registers[1], b'i' as i32);
+
+        let errors = asm
+            .assemble(".entry @nowhere\n.entry @text\n.data\ntext: .byte #1")
+            .unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.message()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "undefined label @nowhere",
+                "the entry point is declared more than once"
+            ]
+        );
+        let errors = asm
+            .assemble(".entry @text\n.data\ntext: .byte #1")
+            .unwrap_err();
+        assert!(matches!(
+            errors[0].kind,
+            AssemblerErrorKind::InvalidDirectiveOperands { .. }
+        ));
+    }
 }
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::assembler::assembler_errors::AssemblerErrorKind;
+use crate::instruction::Opcode;
+
+/// The shape of a single operand slot in an instruction
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OperandKind {
+    /// An integer register such as `$0`, encoded in one byte
+    Register,
+    /// A floating-point register such as `$f0`, encoded in one byte
+    FloatRegister,
+    /// An unsigned integer or label address encoded in one byte
+    Imm8,
+    /// An unsigned integer or label address encoded in two bytes, big-endian
+    Imm16,
+    /// A signed integer encoded in two bytes, big-endian, and sign-extended by the VM
+    SImm16,
+}
+
+use self::OperandKind::*;
+
+impl OperandKind {
+    /// Number of bytes the operand takes in the encoded instruction
+    pub fn size(self) -> usize {
+        match self {
+            Register | FloatRegister | Imm8 => 1,
+            Imm16 | SImm16 => 2,
+        }
+    }
+
+    /// The values an immediate operand can hold, or `None` for register operands
+    pub fn range(self) -> Option<RangeInclusive<i64>> {
+        match self {
+            Register | FloatRegister => None,
+            Imm8 => Some(0..=u8::MAX as i64),
+            Imm16 => Some(0..=u16::MAX as i64),
+            SImm16 => Some(i16::MIN as i64..=i16::MAX as i64),
+        }
+    }
+}
+
+impl fmt::Display for OperandKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register => write!(f, "register"),
+            FloatRegister => write!(f, "float register"),
+            Imm8 => write!(f, "8-bit immediate"),
+            Imm16 => write!(f, "16-bit immediate"),
+            SImm16 => write!(f, "signed 16-bit immediate"),
+        }
+    }
+}
+
+/// Returns `value` if it is inside `range`, or an out of range error
+pub fn check_range(value: i64, range: RangeInclusive<i64>) -> Result<i64, AssemblerErrorKind> {
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(AssemblerErrorKind::ValueOutOfRange {
+            value,
+            min: *range.start(),
+            max: *range.end(),
+        })
+    }
+}
+
+/// Returns the operands `opcode` takes, in order. Every schema fits in the three operand bytes
+/// of an instruction; unused bytes are padded with zeros.
+pub fn operand_schema(opcode: Opcode) -> &'static [OperandKind] {
+    match opcode {
+        Opcode::HLT | Opcode::NOP | Opcode::RET | Opcode::IGL => &[],
+        Opcode::JMP
+        | Opcode::JMPF
+        | Opcode::JMPB
+        | Opcode::JMPE
+        | Opcode::JMPNE
+        | Opcode::JZ
+        | Opcode::JNZ
+        | Opcode::JLT
+        | Opcode::JGE
+        | Opcode::JLE
+        | Opcode::JGT
+        | Opcode::JC
+        | Opcode::JNC
+        | Opcode::JO
+        | Opcode::JNO
+        | Opcode::CALLR
+        | Opcode::ALOC
+        | Opcode::PUSH
+        | Opcode::POP
+        | Opcode::MFR
+        | Opcode::MFSP
+        | Opcode::MTSP => &[Register],
+        Opcode::CALL => &[Imm16],
+        Opcode::LOAD | Opcode::PEEK | Opcode::LUI | Opcode::ORI => &[Register, Imm16],
+        Opcode::ADDI | Opcode::SUBI | Opcode::MULI => &[Register, SImm16],
+        Opcode::EQ
+        | Opcode::NEQ
+        | Opcode::GT
+        | Opcode::GTE
+        | Opcode::LT
+        | Opcode::LTE
+        | Opcode::NOT
+        | Opcode::PUSHM
+        | Opcode::POPM => &[Register, Register],
+        Opcode::ADD
+        | Opcode::SUB
+        | Opcode::MUL
+        | Opcode::DIV
+        | Opcode::MOD
+        | Opcode::AND
+        | Opcode::OR
+        | Opcode::XOR
+        | Opcode::SHL
+        | Opcode::SHR
+        | Opcode::SAR => &[Register, Register, Register],
+        Opcode::LOADB
+        | Opcode::LOADH
+        | Opcode::LOADW
+        | Opcode::STOREB
+        | Opcode::STOREH
+        | Opcode::STOREW
+        | Opcode::DLOADB
+        | Opcode::DLOADH
+        | Opcode::DLOADW
+        | Opcode::SHLI
+        | Opcode::SHRI
+        | Opcode::SARI => &[Register, Register, Imm8],
+        Opcode::LOADF64 => &[FloatRegister, Imm16],
+        Opcode::ADDF64 | Opcode::SUBF64 | Opcode::MULF64 | Opcode::DIVF64 => {
+            &[FloatRegister, FloatRegister, FloatRegister]
+        }
+        Opcode::EQF64
+        | Opcode::NEQF64
+        | Opcode::GTF64
+        | Opcode::GTEF64
+        | Opcode::LTF64
+        | Opcode::LTEF64 => &[FloatRegister, FloatRegister],
+        Opcode::ITOF => &[FloatRegister, Register],
+        Opcode::FTOI => &[Register, FloatRegister],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::INSTRUCTION_SIZE;
+
+    #[test]
+    fn test_every_schema_fits_an_instruction() {
+        for byte in 0..=u8::MAX {
+            let schema = operand_schema(Opcode::from(byte));
+            let size: usize = schema.iter().map(|kind| kind.size()).sum();
+            assert!(size < INSTRUCTION_SIZE, "opcode {} is too wide", byte);
+        }
+    }
+}
@@ -1,9 +1,9 @@
 use crate::assembler::Token;
 use crate::instruction::Opcode;
-use nom::{character::complete::alpha1, IResult};
+use nom::{character::complete::alphanumeric1, IResult};
 
 pub fn opcode(input: &str) -> IResult<&str, Token> {
-    let (input, opcode) = alpha1(input)?;
+    let (input, opcode) = alphanumeric1(input)?;
 
     Ok((
         input,
@@ -13,6 +13,7 @@ pub fn opcode(input: &str) -> IResult<&str, Token> {
     ))
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -26,5 +27,12 @@ mod tests {
         let result = opcode("aold");
         let (_, token) = result.unwrap();
         assert_eq!(token, Token::Op { code: Opcode::IGL });
+        let (_, token) = opcode("addf64").unwrap();
+        assert_eq!(
+            token,
+            Token::Op {
+                code: Opcode::ADDF64
+            }
+        );
     }
 }
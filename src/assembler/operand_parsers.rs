@@ -1,13 +1,142 @@
+use crate::assembler::expression::{expression, Expr};
+use crate::assembler::label_parsers::label_name;
 use crate::assembler::Token;
-use nom::{bytes::complete::tag, character::complete::digit1, combinator::map_res, IResult};
+use std::num::ParseIntError;
 
+use nom::{
+    branch::alt,
+    bytes::complete::{escaped_transform, is_a, is_not, tag},
+    character::complete::{char, digit1, none_of, one_of, space0},
+    combinator::{map, map_res, opt, recognize, value},
+    sequence::{delimited, pair, preceded, tuple},
+    IResult,
+};
+
+/// Runs `parser` and pairs its output with the length of the input it started at. Inputs are
+/// suffixes of one line, so the length locates the output in the line.
+pub fn located<'a, O>(
+    mut parser: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, (O, usize)> {
+    move |input| {
+        let (rest, output) = parser(input)?;
+        Ok((rest, (output, input.len())))
+    }
+}
+
+/// Parses an integer operand: a literal such as `#42`, `#-7`, `#0x1f`, `#0b1010`, `#1_000` or
+/// `#'a'`, the name of an `.equ` constant such as `#BUF_SIZE`, or a parenthesized expression
+/// such as `#(BUF_SIZE * 4 + 1)`. Whether the value fits is checked when it is encoded.
 pub fn integer_operand(input: &str) -> IResult<&str, Token> {
     let (input, _) = tag("#")(input)?;
-    let (input, reg_num) = map_res(digit1, str::parse)(input)?;
+    alt((
+        map(pair(opt(char('-')), number), |(negative, value)| {
+            Token::IntegerOperand {
+                value: if negative.is_some() { -value } else { value },
+            }
+        }),
+        map(
+            delimited(char('('), expression, preceded(space0, char(')'))),
+            |expr| Token::Expression { expr },
+        ),
+        map(label_name, |name| Token::Expression {
+            expr: Expr::Constant(name.to_string()),
+        }),
+    ))(input)
+}
+
+/// Parses a floating-point operand such as `#1.5`, `#-0.25` or `#6.02e23`. A literal needs a
+/// fraction or an exponent, so that `#2` stays an integer.
+pub fn float_operand(input: &str) -> IResult<&str, Token> {
+    let exponent = || tuple((one_of("eE"), opt(one_of("+-")), digit1));
+    let (input, value) = preceded(
+        tag("#"),
+        map_res(
+            recognize(tuple((
+                opt(char('-')),
+                digit1,
+                alt((
+                    recognize(tuple((char('.'), digit1, opt(exponent())))),
+                    recognize(exponent()),
+                )),
+            ))),
+            str::parse::<f64>,
+        ),
+    )(input)?;
+
+    Ok((input, Token::FloatOperand { value }))
+}
+
+/// Parses an unsigned integer literal without the leading `#`. Digits may be separated with `_`.
+pub fn number(input: &str) -> IResult<&str, i64> {
+    alt((
+        char_literal,
+        preceded(
+            tag("0x"),
+            map_res(is_a("0123456789abcdefABCDEF_"), |d| radix(d, 16)),
+        ),
+        preceded(tag("0b"), map_res(is_a("01_"), |d| radix(d, 2))),
+        map_res(recognize(pair(digit1, opt(is_a("0123456789_")))), |d| {
+            radix(d, 10)
+        }),
+    ))(input)
+}
+
+/// Parses digits in the given radix, ignoring `_` separators
+fn radix(digits: &str, radix: u32) -> Result<i64, ParseIntError> {
+    i64::from_str_radix(&digits.replace('_', ""), radix)
+}
+
+/// Parses a character literal such as `'a'` or `'\n'` as its code point
+pub fn char_literal(input: &str) -> IResult<&str, i64> {
+    let (input, c) = delimited(
+        char('\''),
+        alt((
+            preceded(
+                char('\\'),
+                alt((
+                    value('\n', char('n')),
+                    value('\t', char('t')),
+                    value('\0', char('0')),
+                    value('\\', char('\\')),
+                    value('\'', char('\'')),
+                )),
+            ),
+            none_of("\\'"),
+        )),
+        char('\''),
+    )(input)?;
+
+    Ok((input, c as i64))
+}
+
+/// Parses a double-quoted string such as `"hello\n"`. The escapes are `\n`, `\t`, `\0`, `\\`
+/// and `\"`.
+pub fn string_operand(input: &str) -> IResult<&str, Token> {
+    let (input, value) = delimited(
+        char('"'),
+        opt(escaped_transform(
+            is_not("\\\""),
+            '\\',
+            alt((
+                value("\n", char('n')),
+                value("\t", char('t')),
+                value("\0", char('0')),
+                value("\\", char('\\')),
+                value("\"", char('"')),
+            )),
+        )),
+        char('"'),
+    )(input)?;
 
-    Ok((input, Token::IntegerOperand { value: reg_num }))
+    Ok((
+        input,
+        Token::StringOperand {
+            value: value.unwrap_or_default(),
+        },
+    ))
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -19,4 +148,96 @@ mod tests {
 
         assert_eq!(value, Token::IntegerOperand { value: 10 });
     }
+
+    #[test]
+    fn test_negative_integer_operand() {
+        let result = integer_operand("#-123456");
+        assert_eq!(result, Ok(("", Token::IntegerOperand { value: -123456 })));
+        assert!(integer_operand("#-").is_err());
+    }
+
+    #[test]
+    fn test_integer_literal_forms() {
+        let value = |source| match integer_operand(source) {
+            Ok(("", Token::IntegerOperand { value })) => Some(value),
+            _ => None,
+        };
+        assert_eq!(value("#0x1F"), Some(31));
+        assert_eq!(value("#-0x10"), Some(-16));
+        assert_eq!(value("#0b1010"), Some(10));
+        assert_eq!(value("#0b_1111_0000"), Some(0xf0));
+        assert_eq!(value("#1_000_000"), Some(1_000_000));
+        assert_eq!(value("#0xFFFF_FFFF"), Some(0xffff_ffff));
+        assert_eq!(value("#'a'"), Some(97));
+        assert_eq!(value("#'\\n'"), Some(10));
+        assert_eq!(value("#'\\''"), Some(39));
+        assert_eq!(value("#0x"), None);
+        assert_eq!(value("#0b2"), None);
+        assert_eq!(value("#_1"), None);
+        assert_eq!(value("#''"), None);
+        assert_eq!(value("#99999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_float_operand() {
+        let value = |source| match float_operand(source) {
+            Ok(("", Token::FloatOperand { value })) => Some(value),
+            _ => None,
+        };
+        assert_eq!(value("#1.5"), Some(1.5));
+        assert_eq!(value("#-0.25"), Some(-0.25));
+        assert_eq!(value("#6.02e23"), Some(6.02e23));
+        assert_eq!(value("#1E-3"), Some(0.001));
+        assert_eq!(value("#2"), None);
+        assert_eq!(value("#.5"), None);
+        assert_eq!(value("#1."), None);
+    }
+
+    #[test]
+    fn test_expression_operands() {
+        assert_eq!(
+            integer_operand("#BUF_SIZE"),
+            Ok((
+                "",
+                Token::Expression {
+                    expr: Expr::Constant("BUF_SIZE".to_string())
+                }
+            ))
+        );
+        let (rest, token) = integer_operand("#( @end - @start ) $1").unwrap();
+        assert_eq!(rest, " $1");
+        assert!(matches!(token, Token::Expression { .. }));
+        assert!(integer_operand("#(1 + )").is_err());
+        assert!(integer_operand("#(1").is_err());
+    }
+
+    #[test]
+    fn test_string_operand() {
+        let result = string_operand("\"Hello, world!\\n\" rest");
+        assert_eq!(
+            result,
+            Ok((
+                " rest",
+                Token::StringOperand {
+                    value: "Hello, world!\n".to_string()
+                }
+            ))
+        );
+        let (_, token) = string_operand("\"say \\\"hi\\\"\\\\\"").unwrap();
+        assert_eq!(
+            token,
+            Token::StringOperand {
+                value: "say \"hi\"\\".to_string()
+            }
+        );
+        let (_, token) = string_operand("\"\"").unwrap();
+        assert_eq!(
+            token,
+            Token::StringOperand {
+                value: String::new()
+            }
+        );
+        assert!(string_operand("\"unterminated").is_err());
+        assert!(string_operand("\"bad \\q\"").is_err());
+    }
 }
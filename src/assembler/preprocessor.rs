@@ -0,0 +1,859 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::abi::{float_register_number, register_number};
+use crate::assembler::assembler_errors::{
+    AssemblerError, AssemblerErrorKind, MacroCall, SourceLocation,
+};
+use crate::assembler::directive_parsers::operand_separator;
+use crate::assembler::label_parsers::{label_declaration, label_name};
+use crate::assembler::operand_parsers::{char_literal, string_operand};
+use crate::assembler::program_parsers::comment;
+use crate::assembler::register_parsers::register;
+use crate::assembler::Token;
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, space0, space1},
+    combinator::{consumed, eof, opt, recognize},
+    error::{Error, ErrorKind},
+    multi::{many0, separated_list1},
+    sequence::{preceded, terminated, tuple},
+    IResult,
+};
+
+/// Deepest macro calls may nest, counting a macro calling itself, before expansion stops
+pub const MAX_MACRO_DEPTH: usize = 64;
+
+/// A line of source after macro expansion, with the place it came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLine {
+    /// The text to parse, with register aliases replaced
+    pub text: String,
+    /// The text as written, which diagnostics show
+    pub original: String,
+    /// Where aliases in `original` were replaced in `text`, in order
+    pub substitutions: Vec<Substitution>,
+    pub file: String,
+    /// Line number, starting at 1
+    pub line: usize,
+    /// The macro calls the line was expanded from, innermost first
+    pub macro_calls: Vec<MacroCall>,
+    /// Index of the line in the preprocessor's output
+    pub sequence: usize,
+}
+
+/// A span of `SourceLine::original` that was replaced in `SourceLine::text`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Substitution {
+    /// Byte range of the replacement in `text`
+    pub text: Range<usize>,
+    /// Byte range of the replaced span in `original`
+    pub original: Range<usize>,
+}
+
+impl SourceLine {
+    /// Returns a line of `file` as written
+    fn new(text: &str, file: &str, line: usize, macro_calls: Vec<MacroCall>) -> SourceLine {
+        SourceLine {
+            text: text.to_string(),
+            original: text.to_string(),
+            substitutions: vec![],
+            file: file.to_string(),
+            line,
+            macro_calls,
+            sequence: 0,
+        }
+    }
+
+    /// Returns the location of `rest`, which must be a suffix of `text`. The column is that of
+    /// the same code in `original`; within a replaced alias it is the start of the alias.
+    pub fn location(&self, rest: &str) -> SourceLocation {
+        let offset = self.text.len() - rest.len();
+        let column = match self
+            .substitutions
+            .iter()
+            .rev()
+            .find(|substitution| substitution.text.start <= offset)
+        {
+            Some(substitution) if offset < substitution.text.end => substitution.original.start,
+            Some(substitution) => substitution.original.end + offset - substitution.text.end,
+            None => offset,
+        };
+        SourceLocation {
+            file: self.file.clone(),
+            line: self.line,
+            column: column + 1,
+            snippet: self.original.clone(),
+            macro_calls: self.macro_calls.clone(),
+            sequence: self.sequence,
+        }
+    }
+}
+
+/// A `.macro name param1 param2 ...` definition and the lines up to its `.endm`
+#[derive(Clone, Debug)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<SourceLine>,
+    /// Labels declared in the body. Every expansion renames them so that calling the macro
+    /// twice does not declare the same label twice.
+    locals: Vec<String>,
+}
+
+/// Resolves includes, expands macros and replaces register aliases in `source`, which was read
+/// from `file`.
+///
+/// `.include "path"` is replaced by the lines of the named file, resolved relative to the
+/// directory of the including file. A file is only included once; including a file that is
+/// still being read is reported as a cycle.
+///
+/// Macro definitions are removed, and every call is replaced by the macro's body with each
+/// `%param` replaced by the matching argument. A macro must be defined before it is called.
+///
+/// `.alias name $register` lets the rest of the file write `$name` for the register. Aliases
+/// belong to the file that defines them and are not seen by the files it includes or that
+/// include it.
+pub fn preprocess(source: &str, file: &str) -> (Vec<SourceLine>, Vec<AssemblerError>) {
+    let mut preprocessor = Preprocessor::default();
+    if let Ok(path) = fs::canonicalize(file) {
+        preprocessor.included.insert(path.clone());
+        preprocessor.include_stack.push(path);
+    }
+    preprocessor.process(source_lines(source, file), 0);
+    (preprocessor.output, preprocessor.errors)
+}
+
+fn source_lines(source: &str, file: &str) -> Vec<SourceLine> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(index, text)| SourceLine::new(text, file, index + 1, vec![]))
+        .collect()
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    /// Number of expansions so far, used to make local labels unique
+    expansions: usize,
+    /// Every file included so far, canonicalized
+    included: HashSet<PathBuf>,
+    /// The files currently being read, outermost first
+    include_stack: Vec<PathBuf>,
+    /// Register aliases defined with `.alias`, by the file they were defined in
+    aliases: HashMap<String, HashMap<String, u8>>,
+    output: Vec<SourceLine>,
+    errors: Vec<AssemblerError>,
+}
+
+impl Preprocessor {
+    fn process(&mut self, lines: Vec<SourceLine>, depth: usize) {
+        let mut lines = lines.into_iter();
+        while let Some(line) = lines.next() {
+            match first_word(&line.text) {
+                ".macro" => self.define(line, &mut lines),
+                ".endm" => self.error(AssemblerErrorKind::UnmatchedEndm, &line),
+                ".include" => self.include(&line, depth),
+                ".alias" => self.alias(&line),
+                _ => {
+                    let line = self.resolve_aliases(line);
+                    match macro_call(&line.text) {
+                        Ok((_, (prefix, name, args))) if self.macros.contains_key(name) => {
+                            let args = args.into_iter().map(str::to_string).collect();
+                            self.expand(&line, prefix, name.to_string(), args, depth)
+                        }
+                        _ => self.emit(line),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replaces the aliases defined so far in `line`'s file with the registers they name
+    fn resolve_aliases(&self, line: SourceLine) -> SourceLine {
+        match self.aliases.get(&line.file) {
+            Some(aliases) => {
+                let (text, substitutions) = substitute_aliases(&line.text, aliases);
+                SourceLine {
+                    text,
+                    substitutions,
+                    ..line
+                }
+            }
+            None => line,
+        }
+    }
+
+    /// Records the alias defined by `.alias name $register` on `line`. It can be used as
+    /// `$name` on the lines of the same file that follow.
+    fn alias(&mut self, line: &SourceLine) {
+        let line = self.resolve_aliases(line.clone());
+        let (name, register) = match alias_definition(&line.text) {
+            Ok((_, definition)) => definition,
+            Err(_) => {
+                let kind = AssemblerErrorKind::InvalidDirectiveOperands {
+                    directive: "alias".to_string(),
+                    expected: "a name and a register",
+                };
+                return self.error(kind, &line);
+            }
+        };
+        let reg_num = match register {
+            Token::Register { reg_num } => reg_num,
+            Token::UnknownRegister { name } => {
+                return self.error(AssemblerErrorKind::UnknownRegister { name }, &line)
+            }
+            _ => unreachable!("register only returns integer registers"),
+        };
+        if register_number(name).is_some() || float_register_number(name).is_some() {
+            let kind = AssemblerErrorKind::AliasShadowsRegister {
+                name: name.to_string(),
+            };
+            return self.error(kind, &line);
+        }
+
+        let aliases = self.aliases.entry(line.file.clone()).or_default();
+        if aliases.contains_key(name) {
+            let kind = AssemblerErrorKind::DuplicateAlias {
+                name: name.to_string(),
+            };
+            return self.error(kind, &line);
+        }
+        aliases.insert(name.to_string(), reg_num);
+    }
+
+    /// Replaces the `.include` on `line` with the lines of the file it names
+    fn include(&mut self, line: &SourceLine, depth: usize) {
+        let relative = match include_path(&line.text) {
+            Ok((_, relative)) => relative,
+            Err(_) => {
+                let kind = AssemblerErrorKind::InvalidDirectiveOperands {
+                    directive: "include".to_string(),
+                    expected: "a quoted path",
+                };
+                return self.error(kind, line);
+            }
+        };
+        let path = Path::new(&line.file)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(relative);
+        let name = path.to_string_lossy().into_owned();
+        let read_failed = |e: std::io::Error| AssemblerErrorKind::ReadFailed {
+            path: name.clone(),
+            error: e.to_string(),
+        };
+
+        let canonical = match fs::canonicalize(&path) {
+            Ok(canonical) => canonical,
+            Err(e) => return self.error(read_failed(e), line),
+        };
+        if self.include_stack.contains(&canonical) {
+            return self.error(AssemblerErrorKind::IncludeCycle { path: name }, line);
+        }
+        if !self.included.insert(canonical.clone()) {
+            return;
+        }
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => return self.error(read_failed(e), line),
+        };
+
+        self.include_stack.push(canonical);
+        self.process(source_lines(&source, &name), depth);
+        self.include_stack.pop();
+    }
+
+    /// Reads a definition whose `.macro` line is `header` from `lines`, up to its `.endm`
+    fn define(&mut self, header: SourceLine, lines: &mut impl Iterator<Item = SourceLine>) {
+        let (name, params) = match macro_header(&header.text) {
+            Ok((_, (name, params))) => (name.to_string(), params),
+            Err(_) => {
+                let kind = AssemblerErrorKind::InvalidDirectiveOperands {
+                    directive: "macro".to_string(),
+                    expected: "a name followed by parameter names",
+                };
+                self.error(kind, &header);
+                // Skip the body so it is not assembled as ordinary source
+                for line in lines.by_ref() {
+                    if first_word(&line.text) == ".endm" {
+                        break;
+                    }
+                }
+                return;
+            }
+        };
+
+        let mut body = vec![];
+        let mut terminated = false;
+        for line in lines.by_ref() {
+            match first_word(&line.text) {
+                ".endm" => {
+                    terminated = true;
+                    break;
+                }
+                ".macro" => self.error(AssemblerErrorKind::NestedMacro, &line),
+                _ => body.push(line),
+            }
+        }
+        if !terminated {
+            self.error(AssemblerErrorKind::UnterminatedMacro { name }, &header);
+            return;
+        }
+        if self.macros.contains_key(&name) {
+            self.error(AssemblerErrorKind::DuplicateMacro { name }, &header);
+            return;
+        }
+
+        let locals = body
+            .iter()
+            .filter_map(|line| declared_label(&line.text))
+            .collect();
+        let params = params.into_iter().map(str::to_string).collect();
+        self.macros.insert(
+            name,
+            Macro {
+                params,
+                body,
+                locals,
+            },
+        );
+    }
+
+    /// Replaces the call on `line` with the body of macro `name`. `prefix` is the text before
+    /// the macro name, which may declare a label.
+    fn expand(
+        &mut self,
+        line: &SourceLine,
+        prefix: &str,
+        name: String,
+        args: Vec<String>,
+        depth: usize,
+    ) {
+        let location = line.location(&line.text[prefix.len()..]);
+        if depth >= MAX_MACRO_DEPTH {
+            let kind = AssemblerErrorKind::MacroRecursionLimit {
+                name,
+                limit: MAX_MACRO_DEPTH,
+            };
+            self.report(kind, &location);
+            return;
+        }
+        let definition = self.macros[&name].clone();
+        if args.len() != definition.params.len() {
+            let kind = AssemblerErrorKind::MacroArgumentCount {
+                name,
+                expected: definition.params.len(),
+                found: args.len(),
+            };
+            self.report(kind, &location);
+            return;
+        }
+
+        // A label before the call marks the start of the expansion
+        if !prefix.trim().is_empty() {
+            self.emit(SourceLine {
+                text: prefix.to_string(),
+                ..line.clone()
+            });
+        }
+
+        self.expansions += 1;
+        let suffix = format!("__{}_{}", name, self.expansions);
+        let mut macro_calls = vec![MacroCall {
+            name,
+            location: SourceLocation {
+                macro_calls: vec![],
+                ..location
+            },
+        }];
+        macro_calls.extend(line.macro_calls.iter().cloned());
+        let expanded = definition
+            .body
+            .iter()
+            .map(|body_line| {
+                // The body is shown as expanded, which is what the assembler saw
+                let text = substitute(&body_line.text, &definition, &args, &suffix);
+                SourceLine::new(&text, &body_line.file, body_line.line, macro_calls.clone())
+            })
+            .collect();
+        self.process(expanded, depth + 1);
+    }
+
+    /// Adds `line` to the output
+    fn emit(&mut self, line: SourceLine) {
+        let sequence = self.output.len();
+        self.output.push(SourceLine { sequence, ..line });
+    }
+
+    fn error(&mut self, kind: AssemblerErrorKind, line: &SourceLine) {
+        let location = line.location(line.text.trim_start());
+        self.report(kind, &location);
+    }
+
+    /// Records an error at `location`, ordered before the next line of output
+    fn report(&mut self, kind: AssemblerErrorKind, location: &SourceLocation) {
+        let location = SourceLocation {
+            sequence: self.output.len(),
+            ..location.clone()
+        };
+        self.errors.push(AssemblerError::new(kind, &location));
+    }
+}
+
+/// Returns the first word of a line, which is all that is needed to spot `.macro` and `.endm`
+fn first_word(text: &str) -> &str {
+    text.split(|c: char| c.is_whitespace() || c == ';')
+        .find(|word| !word.is_empty())
+        .unwrap_or("")
+}
+
+/// Parses `.include "path"`
+fn include_path(input: &str) -> IResult<&str, String> {
+    let (input, (_, path, _, _, _)) = tuple((
+        preceded(space0, tag(".include")),
+        preceded(space1, string_operand),
+        space0,
+        opt(comment),
+        eof,
+    ))(input)?;
+
+    match path {
+        Token::StringOperand { value } => Ok((input, value)),
+        _ => unreachable!("string_operand only returns strings"),
+    }
+}
+
+/// Parses `.alias name $register`
+fn alias_definition(input: &str) -> IResult<&str, (&str, Token)> {
+    let (input, (_, name, register, _, _, _)) = tuple((
+        preceded(space0, tag(".alias")),
+        preceded(space1, label_name),
+        preceded(operand_separator, register),
+        space0,
+        opt(comment),
+        eof,
+    ))(input)?;
+
+    Ok((input, (name, register)))
+}
+
+/// Parses `.macro name param1 param2 ...`
+fn macro_header(input: &str) -> IResult<&str, (&str, Vec<&str>)> {
+    let (input, (_, name, params, _, _, _)) = tuple((
+        preceded(space0, tag(".macro")),
+        preceded(space1, label_name),
+        many0(preceded(operand_separator, label_name)),
+        space0,
+        opt(comment),
+        eof,
+    ))(input)?;
+
+    Ok((input, (name, params)))
+}
+
+/// Parses a line that may be a macro call: an optional label, a name and its arguments.
+/// Returns the text up to the name, the name, and the argument text.
+fn macro_call(input: &str) -> IResult<&str, (&str, &str, Vec<&str>)> {
+    let (input, ((prefix, _), name, args, _, _, _)) = tuple((
+        consumed(preceded(space0, opt(terminated(label_declaration, space0)))),
+        label_name,
+        opt(preceded(
+            space1,
+            separated_list1(operand_separator, macro_argument),
+        )),
+        space0,
+        opt(comment),
+        eof,
+    ))(input)?;
+
+    Ok((input, (prefix, name, args.unwrap_or_default())))
+}
+
+fn macro_argument(input: &str) -> IResult<&str, &str> {
+    alt((recognize(string_operand), unquoted_argument))(input)
+}
+
+/// Parses an argument up to the next separator, comment or string. Separators inside
+/// parentheses belong to the argument, so `#(SIZE * 2)` is a single argument, and so does
+/// anything inside a character literal, so `#';'` is one too.
+fn unquoted_argument(input: &str) -> IResult<&str, &str> {
+    let mut depth = 0usize;
+    let mut end = 0;
+    while let Some(c) = input[end..].chars().next() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ' ' | '\t' | ',' if depth == 0 => break,
+            ';' | '"' => break,
+            _ => {}
+        }
+        end += char_literal_len(&input[end..]).unwrap_or(c.len_utf8());
+    }
+    if end == 0 {
+        return Err(nom::Err::Error(Error::new(input, ErrorKind::IsNot)));
+    }
+    Ok((&input[end..], &input[..end]))
+}
+
+/// Returns the label declared at the start of `text`, if any
+fn declared_label(text: &str) -> Option<String> {
+    let (_, name) = preceded(space0, terminated(label_name, char(':')))(text).ok()?;
+    Some(name.to_string())
+}
+
+/// Replaces every `%param` in `text` with its argument and appends `suffix` to the macro's
+/// local labels. Strings and comments are copied unchanged.
+fn substitute(text: &str, definition: &Macro, args: &[String], suffix: &str) -> String {
+    rewrite_code(text, |rest, out| {
+        let c = rest.chars().next().unwrap_or_default();
+        if c == '%' || c == '@' {
+            let name = identifier(&rest[1..]);
+            let param = definition.params.iter().position(|param| param == name);
+            match (c, param) {
+                ('%', Some(index)) => out.push_str(&args[index]),
+                ('@', _) if definition.locals.iter().any(|local| local == name) => {
+                    out.push('@');
+                    out.push_str(name);
+                    out.push_str(suffix);
+                }
+                _ => out.push_str(&rest[..name.len() + 1]),
+            }
+            name.len() + 1
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let name = identifier(rest);
+            out.push_str(name);
+            let declaration = out.trim_start() == name && rest[name.len()..].starts_with(':');
+            if declaration && definition.locals.iter().any(|local| local == name) {
+                out.push_str(suffix);
+            }
+            name.len()
+        } else {
+            0
+        }
+    })
+}
+
+/// Replaces every `$alias` in `text` with the register it names, returning the new text and
+/// where each alias was replaced. Strings and comments are copied unchanged.
+fn substitute_aliases(text: &str, aliases: &HashMap<String, u8>) -> (String, Vec<Substitution>) {
+    let mut substitutions = vec![];
+    let substituted = rewrite_code(text, |rest, out| {
+        let Some(name) = rest.strip_prefix('$').map(identifier) else {
+            return 0;
+        };
+        match aliases.get(name) {
+            Some(reg_num) => {
+                let start = out.len();
+                out.push_str(&format!("${}", reg_num));
+                let original = text.len() - rest.len();
+                substitutions.push(Substitution {
+                    text: start..out.len(),
+                    original: original..original + name.len() + 1,
+                });
+            }
+            None => out.push_str(&rest[..name.len() + 1]),
+        }
+        name.len() + 1
+    });
+    (substituted, substitutions)
+}
+
+/// Copies `text`, letting `rewrite` replace code outside of strings, character literals and
+/// comments. `rewrite` is given the rest of the line and the output so far, and returns the
+/// number of bytes it consumed, or 0 to have the next character copied unchanged.
+fn rewrite_code(text: &str, mut rewrite: impl FnMut(&str, &mut String) -> usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut in_string = false;
+    while let Some(c) = rest.chars().next() {
+        let mut len = c.len_utf8();
+        if in_string {
+            if c == '\\' {
+                len += rest[1..].chars().next().map_or(0, char::len_utf8);
+            } else if c == '"' {
+                in_string = false;
+            }
+            out.push_str(&rest[..len]);
+        } else if let Some(literal) = char_literal_len(rest) {
+            len = literal;
+            out.push_str(&rest[..len]);
+        } else if c == ';' {
+            out.push_str(rest);
+            break;
+        } else if c == '"' {
+            in_string = true;
+            out.push(c);
+        } else {
+            match rewrite(rest, &mut out) {
+                0 => out.push(c),
+                consumed => len = consumed,
+            }
+        }
+        rest = &rest[len..];
+    }
+    out
+}
+
+/// Returns the length of the character literal, such as `';'` or `'\''`, at the start of `text`
+fn char_literal_len(text: &str) -> Option<usize> {
+    recognize(char_literal)(text)
+        .ok()
+        .map(|(_, literal)| literal.len())
+}
+
+/// Returns the run of letters, digits and underscores at the start of `text`
+fn identifier(text: &str) -> &str {
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lines: &[SourceLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_expand_with_parameters() {
+        let source = "\
+.macro inc reg, amount ; add a constant
+    addi %reg %amount
+.endm
+start: inc $1, #4
+inc $2 #'%'
+inc $3 #(SIZE * (2 + 1))";
+        let (lines, errors) = preprocess(source, "m.iasm");
+        assert!(errors.is_empty());
+        assert_eq!(
+            texts(&lines),
+            vec![
+                "start: ",
+                "    addi $1 #4",
+                "    addi $2 #'%'",
+                "    addi $3 #(SIZE * (2 + 1))"
+            ]
+        );
+        assert_eq!(lines[1].line, 2);
+        assert_eq!(lines[1].macro_calls[0].name, "inc");
+        assert_eq!(lines[1].macro_calls[0].location.line, 4);
+        assert_eq!(lines[1].macro_calls[0].location.column, 8);
+    }
+
+    #[test]
+    fn test_local_labels_are_unique_per_expansion() {
+        let source = "\
+.macro spin reg
+loop: subi %reg #1
+    jnz @loop ; \"@loop\" in a comment is left alone
+.endm
+spin $1
+spin $2";
+        let (lines, errors) = preprocess(source, "m.iasm");
+        assert!(errors.is_empty());
+        assert_eq!(
+            texts(&lines),
+            vec![
+                "loop__spin_1: subi $1 #1",
+                "    jnz @loop__spin_1 ; \"@loop\" in a comment is left alone",
+                "loop__spin_2: subi $2 #1",
+                "    jnz @loop__spin_2 ; \"@loop\" in a comment is left alone",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_char_literals_are_not_comments_or_strings() {
+        let source = "\
+.macro put value
+    .byte #';', #'\"', %value ; '%value' in a comment is left alone
+.endm
+put #';'
+put #'\"'
+put #'\\''";
+        let (lines, errors) = preprocess(source, "m.iasm");
+        assert!(errors.is_empty());
+        assert_eq!(
+            texts(&lines),
+            vec![
+                "    .byte #';', #'\"', #';' ; '%value' in a comment is left alone",
+                "    .byte #';', #'\"', #'\"' ; '%value' in a comment is left alone",
+                "    .byte #';', #'\"', #'\\'' ; '%value' in a comment is left alone",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_calls_record_every_call_site() {
+        let source = "\
+.macro inner
+    bogus
+.endm
+.macro outer
+    inner
+.endm
+outer";
+        let (lines, errors) = preprocess(source, "m.iasm");
+        assert!(errors.is_empty());
+        assert_eq!(texts(&lines), vec!["    bogus"]);
+        let calls: Vec<_> = lines[0]
+            .macro_calls
+            .iter()
+            .map(|call| (call.name.as_str(), call.location.line))
+            .collect();
+        assert_eq!(calls, vec![("inner", 5), ("outer", 7)]);
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        let source = "\
+.macro forever
+    forever
+.endm
+.macro pair a b
+.endm
+.macro pair
+.endm
+forever
+pair $1
+.endm
+.macro 2bad
+.endm
+.macro open";
+        let (lines, errors) = preprocess(source, "m.iasm");
+        assert!(lines.is_empty());
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                AssemblerErrorKind::DuplicateMacro {
+                    name: "pair".to_string()
+                },
+                AssemblerErrorKind::MacroRecursionLimit {
+                    name: "forever".to_string(),
+                    limit: MAX_MACRO_DEPTH
+                },
+                AssemblerErrorKind::MacroArgumentCount {
+                    name: "pair".to_string(),
+                    expected: 2,
+                    found: 1
+                },
+                AssemblerErrorKind::UnmatchedEndm,
+                AssemblerErrorKind::InvalidDirectiveOperands {
+                    directive: "macro".to_string(),
+                    expected: "a name followed by parameter names"
+                },
+                AssemblerErrorKind::UnterminatedMacro {
+                    name: "open".to_string()
+                },
+            ]
+        );
+        assert_eq!(errors[1].line, 2);
+        assert_eq!(
+            errors[1].to_string(),
+            "\
+m.iasm:2:5: error: expanding macro forever nests more than 64 macro calls deep
+2 |     forever
+  |     ^
+m.iasm:2:5: note: in expansion of macro forever (63 times)
+2 |     forever
+  |     ^
+m.iasm:8:1: note: in expansion of macro forever
+8 | forever
+  | ^"
+        );
+
+        // Calls that alternate cannot be collapsed, so the notes are cut short
+        let source = ".macro ping\n    pong\n.endm\n.macro pong\n    ping\n.endm\nping";
+        let (_, errors) = preprocess(source, "m.iasm");
+        let message = errors[0].to_string();
+        assert_eq!(message.matches("note: in expansion").count(), 8);
+        assert!(message.ends_with("note: ... 56 more macro expansions"));
+    }
+
+    #[test]
+    fn test_aliases() {
+        let source = "\
+.alias counter $s0 ; loop counter
+.alias limit, $counter
+.macro bump reg
+    addi %reg #1
+.endm
+loop: bump $counter
+    lt $counter $limit
+    .asciiz \"$counter\" ; $counter";
+        let (lines, errors) = preprocess(source, "a.iasm");
+        assert!(errors.is_empty());
+        assert_eq!(
+            texts(&lines),
+            vec![
+                "loop: ",
+                "    addi $22 #1",
+                "    lt $22 $22",
+                "    .asciiz \"$counter\" ; $counter"
+            ]
+        );
+
+        // Diagnostics show the line as written, with columns in it
+        let (lines, _) = preprocess(".alias counter $3\nadd $counter $counter #1", "a.iasm");
+        let location = lines[0].location(&lines[0].text[10..]);
+        assert_eq!(lines[0].text, "add $3 $3 #1");
+        assert_eq!(location.snippet, "add $counter $counter #1");
+        assert_eq!(location.column, 23);
+        assert_eq!(lines[0].location(&lines[0].text[5..]).column, 5);
+
+        // Aliases do not leak into other files
+        let mut preprocessor = Preprocessor::default();
+        let mut lines = source_lines(".alias counter $3", "a.iasm");
+        lines.extend(source_lines("push $counter", "b.iasm"));
+        preprocessor.process(lines, 0);
+        assert_eq!(texts(&preprocessor.output), vec!["push $counter"]);
+    }
+
+    #[test]
+    fn test_alias_errors() {
+        let source = "\
+.alias a1 $3
+.alias f0 $3
+.alias x $40
+.alias y
+.alias z $f1
+.alias z $1
+.alias z $2";
+        let (lines, errors) = preprocess(source, "a.iasm");
+        assert!(lines.is_empty());
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind.clone()).collect();
+        let invalid = AssemblerErrorKind::InvalidDirectiveOperands {
+            directive: "alias".to_string(),
+            expected: "a name and a register",
+        };
+        assert_eq!(
+            kinds,
+            vec![
+                AssemblerErrorKind::AliasShadowsRegister {
+                    name: "a1".to_string()
+                },
+                AssemblerErrorKind::AliasShadowsRegister {
+                    name: "f0".to_string()
+                },
+                AssemblerErrorKind::UnknownRegister {
+                    name: "40".to_string()
+                },
+                invalid.clone(),
+                invalid,
+                AssemblerErrorKind::DuplicateAlias {
+                    name: "z".to_string()
+                },
+            ]
+        );
+        assert_eq!(errors[5].line, 7);
+    }
+}
@@ -1,48 +1,213 @@
+use crate::assembler::assembler_errors::{
+    AssemblerError, AssemblerErrorKind, SourceLocation, StatementError,
+};
+use crate::assembler::directive_parsers::{directive, Directive};
 use crate::assembler::instruction_parsers::{instruction, AssemblerInstruction};
+use crate::assembler::preprocessor::preprocess;
+use crate::assembler::symbols::{Section, SymbolTable};
 
-use nom::{multi::many1, IResult};
+use nom::{
+    branch::alt,
+    character::complete::{char, line_ending, not_line_ending, space0},
+    combinator::{consumed, eof, map, opt, verify},
+    sequence::{delimited, preceded, tuple},
+    IResult,
+};
 
+/// Builds a parser for one line of source: optional indentation, an optional `statement`,
+/// optional trailing space and `;` comment, then the line ending or the end of the input. The
+/// parser fails rather than match an empty input.
+pub fn line<'a, O>(
+    statement: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Option<O>> {
+    map(
+        verify(
+            consumed(delimited(
+                space0,
+                opt(statement),
+                tuple((space0, opt(comment), alt((line_ending, eof)))),
+            )),
+            |(text, _): &(&str, Option<O>)| !text.is_empty(),
+        ),
+        |(_, statement)| statement,
+    )
+}
+
+/// Parses a comment, which runs from `;` to the end of the line
+pub fn comment(input: &str) -> IResult<&str, &str> {
+    preceded(char(';'), not_line_ending)(input)
+}
+
+/// What a single line of source holds
 #[derive(Debug, PartialEq)]
-pub struct Program {
-    instructions: Vec<AssemblerInstruction>,
+pub enum StatementKind {
+    Instruction(AssemblerInstruction),
+    Directive(Directive),
 }
 
-pub fn program(input: &str) -> IResult<&str, Program> {
-    let (input, instructions) = many1(instruction)(input)?;
+/// An instruction or directive and the place in the source it was parsed from
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub location: SourceLocation,
+    /// Where each operand starts, so errors in an operand can point at it
+    pub operand_locations: Vec<SourceLocation>,
+}
+
+impl Statement {
+    /// Returns the name of the label declared on this statement, if any
+    pub fn label_name(&self) -> Option<&str> {
+        match &self.kind {
+            StatementKind::Instruction(instruction) => instruction.label_name(),
+            StatementKind::Directive(directive) => directive.label_name(),
+        }
+    }
+
+    /// Returns the section this statement switches to, if it is `.code` or `.data`
+    pub fn section(&self) -> Option<Section> {
+        match &self.kind {
+            StatementKind::Directive(directive) => directive.section(),
+            StatementKind::Instruction(_) => None,
+        }
+    }
+
+    /// Returns the section this statement is placed in when the current section is `section`
+    pub fn placement(&self, section: Section) -> Section {
+        match &self.kind {
+            StatementKind::Directive(directive) => directive.placement().unwrap_or(section),
+            StatementKind::Instruction(_) => section,
+        }
+    }
+
+    /// Number of bytes this statement adds to its section
+    pub fn size(&self, symbols: &SymbolTable) -> Result<usize, StatementError> {
+        match &self.kind {
+            StatementKind::Instruction(instruction) => instruction.size(symbols),
+            StatementKind::Directive(directive) => directive.size(symbols),
+        }
+    }
 
-    Ok((input, Program { instructions }))
+    /// Builds the error for `error`, pointing at its operand if it names one
+    pub fn error(&self, error: StatementError) -> AssemblerError {
+        let location = error
+            .operand
+            .and_then(|operand| self.operand_locations.get(operand))
+            .unwrap_or(&self.location);
+        AssemblerError::new(error.kind, location)
+    }
 }
 
-impl Program {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut program = vec![];
-        for instruction in &self.instructions {
-            program.append(&mut instruction.to_bytes());
+impl StatementKind {
+    /// For each operand, the length of the line from the operand's start
+    fn operand_positions(&self) -> &[usize] {
+        match self {
+            StatementKind::Instruction(instruction) => instruction.operand_positions(),
+            StatementKind::Directive(directive) => directive.operand_positions(),
         }
+    }
+}
+
+fn statement_kind(input: &str) -> IResult<&str, StatementKind> {
+    alt((
+        map(directive, StatementKind::Directive),
+        map(instruction, StatementKind::Instruction),
+    ))(input)
+}
 
-        program
+/// Expands macros in `source` and then parses it one line at a time, so that a line that does
+/// not parse is reported and skipped instead of ending the parse. Returns the statements that
+/// parsed and an error for every line that did not. Lines may end in LF or CRLF.
+pub fn statements(source: &str, file: &str) -> (Vec<Statement>, Vec<AssemblerError>) {
+    let (lines, mut errors) = preprocess(source, file);
+    let mut statements = vec![];
+    for source_line in &lines {
+        let text = source_line.text.as_str();
+        if text.is_empty() {
+            continue;
+        }
+        match line(statement_kind)(text) {
+            Ok((_, None)) => {}
+            Ok((_, Some(kind))) => {
+                let operand_locations = kind
+                    .operand_positions()
+                    .iter()
+                    .map(|position| source_line.location(&text[text.len() - position..]))
+                    .collect();
+                statements.push(Statement {
+                    kind,
+                    location: source_line.location(text.trim_start()),
+                    operand_locations,
+                })
+            }
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                let error = if e.input == text.trim_start() {
+                    "expected an instruction, directive or label".to_string()
+                } else {
+                    format!("unexpected `{}`", e.input.trim_end())
+                };
+                errors.push(AssemblerError::new(
+                    AssemblerErrorKind::ParseError { error },
+                    &source_line.location(e.input),
+                ))
+            }
+            Err(nom::Err::Incomplete(_)) => errors.push(AssemblerError::new(
+                AssemblerErrorKind::ParseError {
+                    error: "unexpected end of line".to_string(),
+                },
+                &source_line.location(""),
+            )),
+        }
     }
+    (statements, errors)
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_parse_program() {
-        let result = program("load $0 #100");
-        assert!(result.is_ok());
-        let (leftover, p) = result.unwrap();
-        assert_eq!(leftover, "");
-        assert_eq!(1, p.instructions.len());
+        let (statements, errors) = statements("load $0 #100", "t.iasm");
+        assert!(errors.is_empty());
+        assert_eq!(statements.len(), 1);
+        assert!(matches!(statements[0].kind, StatementKind::Instruction(_)));
+    }
+
+    #[test]
+    fn test_statements_report_every_bad_line() {
+        let (statements, errors) =
+            statements("load $0 #1\n\n$1 add\nhlt\nload $0 #1 junk\n", "t.iasm");
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[1].location.line, 4);
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].column), (3, 1));
+        assert_eq!(errors[0].snippet, "$1 add");
+        assert_eq!((errors[1].line, errors[1].column), (5, 12));
+        assert_eq!(errors[1].file, "t.iasm");
     }
 
     #[test]
-    fn test_program_to_bytes() {
-        let result = program("load $0 #100");
-        assert!(result.is_ok());
-        let (_, program) = result.unwrap();
-        let bytecode = program.to_bytes();
-        assert_eq!(bytecode.len(), 4);
-        println!("{:?}", bytecode);
+    fn test_comments_blank_lines_and_indentation() {
+        let source = "; a counting loop\r\n\r\n    load $0 #3 ; counter\r\n\tloop: ; top\r\n  subi $0 #1\r\n   \r\nhlt;done";
+        let (statements, errors) = statements(source, "t.iasm");
+        assert!(errors.is_empty());
+        assert_eq!(statements.len(), 4);
+        assert_eq!(statements[1].label_name(), Some("loop"));
+        assert_eq!(statements[2].label_name(), None);
+        assert_eq!(statements[0].location.line, 3);
+        assert_eq!(statements[0].location.column, 5);
+        assert_eq!(statements[0].location.snippet, "    load $0 #3 ; counter");
+    }
+
+    #[test]
+    fn test_semicolon_inside_string_is_not_a_comment() {
+        let (statements, errors) = statements(".asciiz \"a;b\" ; the string", "t.iasm");
+        assert!(errors.is_empty());
+        match &statements[0].kind {
+            StatementKind::Directive(directive) => {
+                assert_eq!(directive.size(&SymbolTable::new()), Ok(4))
+            }
+            kind => panic!("expected a directive, got {:?}", kind),
+        }
     }
 }
@@ -1,13 +1,52 @@
+use crate::abi::{float_register_number, register_number};
 use crate::assembler::Token;
-use nom::{bytes::complete::tag, character::complete::digit1, combinator::map_res, IResult};
+use nom::{
+    bytes::complete::{tag, take_while1},
+    character::complete::digit1,
+    combinator::{not, recognize},
+    sequence::{pair, preceded, terminated},
+    IResult,
+};
 
+/// Parses an integer register written as `$3` or by its ABI name, such as `$a0`. Any other
+/// name, apart from a float register, is returned as an unknown register so that it can be
+/// reported as one.
 pub fn register(input: &str) -> IResult<&str, Token> {
     let (input, _) = tag("$")(input)?;
-    let (input, reg_num) = map_res(digit1, str::parse)(input)?;
+    not(terminated(pair(tag("f"), digit1), not(word_char)))(input)?;
+    let (input, name) = take_while1(is_word_char)(input)?;
 
-    Ok((input, Token::Register { reg_num }))
+    let token = match register_number(name) {
+        Some(reg_num) => Token::Register { reg_num },
+        None => Token::UnknownRegister {
+            name: name.to_string(),
+        },
+    };
+    Ok((input, token))
 }
 
+/// Parses a floating-point register written as `$f0`
+pub fn float_register(input: &str) -> IResult<&str, Token> {
+    let (input, name) = preceded(tag("$"), recognize(pair(tag("f"), digit1)))(input)?;
+
+    let token = match float_register_number(name) {
+        Some(reg_num) => Token::FloatRegister { reg_num },
+        None => Token::UnknownRegister {
+            name: name.to_string(),
+        },
+    };
+    Ok((input, token))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn word_char(input: &str) -> IResult<&str, &str> {
+    take_while1(is_word_char)(input)
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -18,6 +57,62 @@ mod tests {
         let result = register("0");
         assert!(result.is_err());
         let result = register("$a");
+        assert_eq!(
+            result,
+            Ok((
+                "",
+                Token::UnknownRegister {
+                    name: "a".to_string()
+                }
+            ))
+        );
+        assert_eq!(register("$31"), Ok(("", Token::Register { reg_num: 31 })));
+        assert_eq!(
+            register("$32"),
+            Ok((
+                "",
+                Token::UnknownRegister {
+                    name: "32".to_string()
+                }
+            ))
+        );
+        assert!(matches!(
+            register("$256"),
+            Ok(("", Token::UnknownRegister { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_parse_abi_register() {
+        assert_eq!(register("$a0 "), Ok((" ", Token::Register { reg_num: 2 })));
+        assert_eq!(register("$s9"), Ok(("", Token::Register { reg_num: 31 })));
+        assert_eq!(
+            register("$fp"),
+            Ok((
+                "",
+                Token::UnknownRegister {
+                    name: "fp".to_string()
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_float_register() {
+        let result = float_register("$f3");
+        assert_eq!(result, Ok(("", Token::FloatRegister { reg_num: 3 })));
+        let result = float_register("$3");
+        assert!(result.is_err());
+        let result = register("$f3");
         assert!(result.is_err());
+        assert_eq!(
+            float_register("$f32"),
+            Ok((
+                "",
+                Token::UnknownRegister {
+                    name: "f32".to_string()
+                }
+            ))
+        );
     }
 }
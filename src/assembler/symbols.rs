@@ -0,0 +1,141 @@
+use std::fmt;
+
+use crate::assembler::assembler_errors::AssemblerErrorKind;
+
+/// The part of the assembled output a statement is placed in
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Section {
+    /// Instructions, loaded as the VM's program
+    #[default]
+    Code,
+    /// Constant data, loaded as the VM's read-only data area
+    Data,
+    /// Float constants declared with `.double`, loaded as the VM's constant pool
+    Constants,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Section::Code => write!(f, "code"),
+            Section::Data => write!(f, "data"),
+            Section::Constants => write!(f, "constants"),
+        }
+    }
+}
+
+/// A named address in the assembled program
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    /// Byte offset of the symbol from the start of its section, or the index of a constant
+    pub offset: usize,
+    /// The section the symbol was declared in
+    pub section: Section,
+}
+
+/// A name given a value with `.equ`
+#[derive(Debug, PartialEq)]
+pub struct Constant {
+    pub name: String,
+    pub value: i64,
+}
+
+/// The labels and constants declared in a program, in declaration order
+#[derive(Debug, Default, PartialEq)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    constants: Vec<Constant>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            symbols: vec![],
+            constants: vec![],
+        }
+    }
+
+    pub fn add_symbol(&mut self, symbol: Symbol) {
+        self.symbols.push(symbol);
+    }
+
+    pub fn has_symbol(&self, name: &str) -> bool {
+        self.symbols.iter().any(|symbol| symbol.name == name)
+    }
+
+    /// Returns the symbol called `name`, if it has been declared
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Returns the label called `name`, or an error naming it if it has not been declared
+    pub fn label(&self, name: &str) -> Result<&Symbol, AssemblerErrorKind> {
+        self.symbol(name)
+            .ok_or_else(|| AssemblerErrorKind::UnknownLabel {
+                name: name.to_string(),
+            })
+    }
+
+    /// Returns the offset of the label called `name`, which must be in the code section
+    pub fn code_label(&self, name: &str) -> Result<usize, AssemblerErrorKind> {
+        match self.label(name)? {
+            symbol if symbol.section == Section::Code => Ok(symbol.offset),
+            _ => Err(AssemblerErrorKind::DataLabelAsCodeAddress {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Returns the offset of the symbol called `name`, if it has been declared
+    pub fn symbol_value(&self, name: &str) -> Option<usize> {
+        self.symbol(name).map(|symbol| symbol.offset)
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn add_constant(&mut self, name: String, value: i64) {
+        self.constants.push(Constant { name, value });
+    }
+
+    pub fn has_constant(&self, name: &str) -> bool {
+        self.constants.iter().any(|constant| constant.name == name)
+    }
+
+    /// Returns the value of the constant called `name`, if it has been defined
+    pub fn constant_value(&self, name: &str) -> Option<i64> {
+        self.constants
+            .iter()
+            .find(|constant| constant.name == name)
+            .map(|constant| constant.value)
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_table() {
+        let mut table = SymbolTable::new();
+        table.add_symbol(Symbol {
+            name: "test".to_string(),
+            offset: 12,
+            section: Section::Data,
+        });
+        assert!(table.has_symbol("test"));
+        assert_eq!(table.symbol_value("test"), Some(12));
+        assert_eq!(table.symbol_value("other"), None);
+
+        table.add_constant("SIZE".to_string(), -4);
+        assert!(table.has_constant("SIZE"));
+        assert!(!table.has_constant("test"));
+        assert_eq!(table.constant_value("SIZE"), Some(-4));
+    }
+}
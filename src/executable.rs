@@ -0,0 +1,464 @@
+//! The on-disk executable format written by the assembler and loaded by the VM. All integers
+//! are big-endian, like instruction immediates.
+//!
+//! | Offset | Size | Field                                               |
+//! |--------|------|-----------------------------------------------------|
+//! | 0      | 4    | Magic number, `IRDM`                                |
+//! | 4      | 2    | ISA version, see [`ISA_VERSION`]                    |
+//! | 6      | 2    | Number of entries in the section table              |
+//! | 8      | 4    | Entry point, as a byte offset into the code section |
+//! | 12     | 12n  | Section table                                       |
+//!
+//! Each section table entry is a [`SectionKind`] (4 bytes), followed by the offset of the
+//! section from the start of the file (4 bytes) and its length (4 bytes). Every kind may appear
+//! at most once, and the code section must be present.
+//!
+//! The constants section holds the float constant pool as 8-byte IEEE 754 doubles. The symbol
+//! section lists every label as its section kind (1 byte), its offset within that section, or
+//! its index for a constant (4 bytes), the length of its name (1 byte) and the name in UTF-8.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::vm::INSTRUCTION_SIZE;
+
+/// Identifies an iridium executable
+pub const MAGIC: [u8; 4] = *b"IRDM";
+
+/// Version of the instruction set and file layout. A file with any other version is rejected.
+pub const ISA_VERSION: u16 = 1;
+
+/// Size of the fixed header, before the section table
+pub const HEADER_SIZE: usize = 12;
+
+/// Size of one section table entry
+pub const SECTION_ENTRY_SIZE: usize = 12;
+
+/// Longest symbol name, in bytes, that the symbol section can hold
+pub const MAX_SYMBOL_NAME_LEN: usize = u8::MAX as usize;
+
+/// The kinds of section an executable can contain
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SectionKind {
+    /// Instructions, loaded as the VM's program
+    Code = 1,
+    /// Read-only data, loaded as the VM's data area
+    Data = 2,
+    /// Label names and offsets, for debugging and disassembly
+    Symbols = 3,
+    /// Float constants, loaded as the VM's constant pool
+    Constants = 4,
+}
+
+impl SectionKind {
+    fn from_u32(kind: u32) -> Option<SectionKind> {
+        match kind {
+            1 => Some(SectionKind::Code),
+            2 => Some(SectionKind::Data),
+            3 => Some(SectionKind::Symbols),
+            4 => Some(SectionKind::Constants),
+            _ => None,
+        }
+    }
+}
+
+/// A label recorded in the symbol section
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutableSymbol {
+    pub name: String,
+    /// `SectionKind::Code`, `SectionKind::Data` or `SectionKind::Constants`
+    pub section: SectionKind,
+    /// Byte offset of the label from the start of its section, or the index of a constant
+    pub offset: u32,
+}
+
+/// The contents of an executable file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Executable {
+    /// Byte offset into `code` that execution starts at
+    pub entry: u32,
+    pub code: Vec<u8>,
+    pub data: Vec<u8>,
+    pub float_constants: Vec<f64>,
+    pub symbols: Vec<ExecutableSymbol>,
+}
+
+/// Why an executable could not be loaded
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadError {
+    /// The file does not start with the iridium magic number
+    BadMagic,
+    /// The file was built for a different version of the instruction set
+    UnsupportedVersion { found: u16, supported: u16 },
+    /// The file ends before the header or section table does
+    Truncated,
+    /// A section table entry has a kind this version does not know
+    UnknownSection { kind: u32 },
+    /// A section kind appears more than once in the section table
+    DuplicateSection { kind: SectionKind },
+    /// A section extends past the end of the file
+    SectionOutOfBounds { kind: SectionKind },
+    /// The file has no code section
+    MissingCode,
+    /// The entry point is not an instruction in the code section
+    InvalidEntryPoint { entry: u32 },
+    /// The symbol section could not be decoded
+    MalformedSymbols,
+    /// The constants section is not a whole number of doubles
+    MalformedConstants,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::BadMagic => write!(f, "not an iridium executable"),
+            LoadError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "executable is for ISA version {}, but this VM supports version {}",
+                found, supported
+            ),
+            LoadError::Truncated => write!(f, "executable header is truncated"),
+            LoadError::UnknownSection { kind } => write!(f, "unknown section kind {}", kind),
+            LoadError::DuplicateSection { kind } => {
+                write!(f, "{:?} section appears more than once", kind)
+            }
+            LoadError::SectionOutOfBounds { kind } => {
+                write!(f, "{:?} section extends past the end of the file", kind)
+            }
+            LoadError::MissingCode => write!(f, "executable has no code section"),
+            LoadError::InvalidEntryPoint { entry } => {
+                write!(f, "entry point {} is not an instruction in the code", entry)
+            }
+            LoadError::MalformedSymbols => write!(f, "symbol section is malformed"),
+            LoadError::MalformedConstants => write!(f, "constants section is malformed"),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// Why an executable could not be encoded
+#[derive(Clone, Debug, PartialEq)]
+pub enum WriteError {
+    /// A symbol name is longer than the symbol section can hold
+    SymbolNameTooLong { name: String, max: usize },
+    /// A section does not fit in the 32-bit offsets and lengths of the section table
+    SectionTooLarge { kind: SectionKind },
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WriteError::SymbolNameTooLong { name, max } => write!(
+                f,
+                "symbol name {} is longer than the limit of {} bytes",
+                name, max
+            ),
+            WriteError::SectionTooLarge { kind } => {
+                write!(f, "{:?} section is too large for an executable", kind)
+            }
+        }
+    }
+}
+
+impl Error for WriteError {}
+
+impl Executable {
+    /// Encodes the executable, writing only the sections that are not empty. The code section
+    /// is always written. Fails if a symbol name is longer than `MAX_SYMBOL_NAME_LEN` bytes or
+    /// the file would be larger than 32-bit offsets can address.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, WriteError> {
+        let mut symbols = vec![];
+        for symbol in &self.symbols {
+            let name = symbol.name.as_bytes();
+            if name.len() > MAX_SYMBOL_NAME_LEN {
+                return Err(WriteError::SymbolNameTooLong {
+                    name: symbol.name.clone(),
+                    max: MAX_SYMBOL_NAME_LEN,
+                });
+            }
+            symbols.push(symbol.section as u8);
+            symbols.extend_from_slice(&symbol.offset.to_be_bytes());
+            symbols.push(name.len() as u8);
+            symbols.extend_from_slice(name);
+        }
+        let constants: Vec<u8> = self
+            .float_constants
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        let sections: Vec<(SectionKind, &[u8])> = [
+            (SectionKind::Code, self.code.as_slice()),
+            (SectionKind::Data, self.data.as_slice()),
+            (SectionKind::Constants, constants.as_slice()),
+            (SectionKind::Symbols, symbols.as_slice()),
+        ]
+        .into_iter()
+        .filter(|(kind, contents)| *kind == SectionKind::Code || !contents.is_empty())
+        .collect();
+
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&ISA_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&(sections.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.entry.to_be_bytes());
+        let mut offset = HEADER_SIZE + sections.len() * SECTION_ENTRY_SIZE;
+        for (kind, contents) in &sections {
+            let end = offset + contents.len();
+            if u32::try_from(end).is_err() {
+                return Err(WriteError::SectionTooLarge { kind: *kind });
+            }
+            bytes.extend_from_slice(&(*kind as u32).to_be_bytes());
+            bytes.extend_from_slice(&(offset as u32).to_be_bytes());
+            bytes.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+            offset = end;
+        }
+        for (_, contents) in &sections {
+            bytes.extend_from_slice(contents);
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes and validates an executable
+    pub fn from_bytes(bytes: &[u8]) -> Result<Executable, LoadError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+        let header = bytes.get(..HEADER_SIZE).ok_or(LoadError::Truncated)?;
+        let version = u16::from_be_bytes([header[4], header[5]]);
+        if version != ISA_VERSION {
+            return Err(LoadError::UnsupportedVersion {
+                found: version,
+                supported: ISA_VERSION,
+            });
+        }
+        let section_count = u16::from_be_bytes([header[6], header[7]]) as usize;
+        let entry = read_u32(&header[8..]);
+        let table = bytes
+            .get(HEADER_SIZE..HEADER_SIZE + section_count * SECTION_ENTRY_SIZE)
+            .ok_or(LoadError::Truncated)?;
+
+        let mut executable = Executable {
+            entry,
+            ..Executable::default()
+        };
+        let mut seen = vec![];
+        for entry in table.chunks_exact(SECTION_ENTRY_SIZE) {
+            let kind = read_u32(entry);
+            let kind = SectionKind::from_u32(kind).ok_or(LoadError::UnknownSection { kind })?;
+            if seen.contains(&kind) {
+                return Err(LoadError::DuplicateSection { kind });
+            }
+            seen.push(kind);
+            let start = read_u32(&entry[4..]) as usize;
+            let len = read_u32(&entry[8..]) as usize;
+            let contents = start
+                .checked_add(len)
+                .and_then(|end| bytes.get(start..end))
+                .ok_or(LoadError::SectionOutOfBounds { kind })?;
+            match kind {
+                SectionKind::Code => executable.code = contents.to_vec(),
+                SectionKind::Data => executable.data = contents.to_vec(),
+                SectionKind::Symbols => executable.symbols = read_symbols(contents)?,
+                SectionKind::Constants => executable.float_constants = read_constants(contents)?,
+            }
+        }
+
+        if !seen.contains(&SectionKind::Code) {
+            return Err(LoadError::MissingCode);
+        }
+        // An empty program can only start at 0
+        let entry = executable.entry as usize;
+        let misaligned = !entry.is_multiple_of(INSTRUCTION_SIZE);
+        if misaligned || (entry >= executable.code.len() && entry != 0) {
+            return Err(LoadError::InvalidEntryPoint {
+                entry: executable.entry,
+            });
+        }
+        Ok(executable)
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_constants(contents: &[u8]) -> Result<Vec<f64>, LoadError> {
+    let chunks = contents.chunks_exact(8);
+    if !chunks.remainder().is_empty() {
+        return Err(LoadError::MalformedConstants);
+    }
+    Ok(chunks
+        .map(|chunk| f64::from_be_bytes(chunk.try_into().expect("chunks are 8 bytes")))
+        .collect())
+}
+
+fn read_symbols(mut contents: &[u8]) -> Result<Vec<ExecutableSymbol>, LoadError> {
+    let mut symbols = vec![];
+    while !contents.is_empty() {
+        let header = contents.get(..6).ok_or(LoadError::MalformedSymbols)?;
+        let section = match SectionKind::from_u32(header[0] as u32) {
+            Some(section @ (SectionKind::Code | SectionKind::Data | SectionKind::Constants)) => {
+                section
+            }
+            _ => return Err(LoadError::MalformedSymbols),
+        };
+        let offset = read_u32(&header[1..]);
+        let name_len = header[5] as usize;
+        let name = contents
+            .get(6..6 + name_len)
+            .ok_or(LoadError::MalformedSymbols)?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| LoadError::MalformedSymbols)?;
+        symbols.push(ExecutableSymbol {
+            name,
+            section,
+            offset,
+        });
+        contents = &contents[6 + name_len..];
+    }
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn executable() -> Executable {
+        Executable {
+            entry: 4,
+            code: vec![0, 1, 0, 5, 5, 0, 0, 0],
+            data: vec![b'h', b'i', 0],
+            float_constants: vec![1.5, -0.25],
+            symbols: vec![
+                ExecutableSymbol {
+                    name: "main".to_string(),
+                    section: SectionKind::Code,
+                    offset: 4,
+                },
+                ExecutableSymbol {
+                    name: "greeting".to_string(),
+                    section: SectionKind::Data,
+                    offset: 0,
+                },
+                ExecutableSymbol {
+                    name: "quarter".to_string(),
+                    section: SectionKind::Constants,
+                    offset: 1,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = executable().to_bytes().unwrap();
+        assert_eq!(&bytes[..4], b"IRDM");
+        assert_eq!(&bytes[4..6], &[0, 1]);
+        assert_eq!(&bytes[6..8], &[0, 4]);
+        assert_eq!(Executable::from_bytes(&bytes), Ok(executable()));
+
+        let code_only = Executable {
+            code: vec![5, 0, 0, 0],
+            ..Executable::default()
+        };
+        let bytes = code_only.to_bytes().unwrap();
+        assert_eq!(bytes.len(), HEADER_SIZE + SECTION_ENTRY_SIZE + 4);
+        assert_eq!(Executable::from_bytes(&bytes), Ok(code_only));
+
+        let mut long_name = executable();
+        long_name.symbols[0].name = "x".repeat(MAX_SYMBOL_NAME_LEN + 1);
+        assert_eq!(
+            long_name.to_bytes(),
+            Err(WriteError::SymbolNameTooLong {
+                name: long_name.symbols[0].name.clone(),
+                max: MAX_SYMBOL_NAME_LEN
+            })
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        let bytes = executable().to_bytes().unwrap();
+        assert_eq!(Executable::from_bytes(b"ELF"), Err(LoadError::BadMagic));
+        assert_eq!(
+            Executable::from_bytes(&bytes[..10]),
+            Err(LoadError::Truncated)
+        );
+        assert_eq!(
+            Executable::from_bytes(&bytes[..20]),
+            Err(LoadError::Truncated)
+        );
+
+        let mut newer = bytes.clone();
+        newer[5] = 2;
+        let error = Executable::from_bytes(&newer).unwrap_err();
+        assert_eq!(
+            error,
+            LoadError::UnsupportedVersion {
+                found: 2,
+                supported: ISA_VERSION
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "executable is for ISA version 2, but this VM supports version 1"
+        );
+
+        let mut unknown = bytes.clone();
+        unknown[HEADER_SIZE + 3] = 9;
+        assert_eq!(
+            Executable::from_bytes(&unknown),
+            Err(LoadError::UnknownSection { kind: 9 })
+        );
+
+        let mut duplicate = bytes.clone();
+        duplicate[HEADER_SIZE + SECTION_ENTRY_SIZE + 3] = 1;
+        assert_eq!(
+            Executable::from_bytes(&duplicate),
+            Err(LoadError::DuplicateSection {
+                kind: SectionKind::Code
+            })
+        );
+
+        assert_eq!(
+            Executable::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(LoadError::SectionOutOfBounds {
+                kind: SectionKind::Symbols
+            })
+        );
+
+        let mut no_code = bytes.clone();
+        no_code[7] = 0;
+        assert_eq!(
+            Executable::from_bytes(&no_code),
+            Err(LoadError::MissingCode)
+        );
+
+        for entry in [2, 8] {
+            let misplaced = Executable {
+                entry,
+                ..executable()
+            };
+            assert_eq!(
+                Executable::from_bytes(&misplaced.to_bytes().unwrap()),
+                Err(LoadError::InvalidEntryPoint { entry })
+            );
+        }
+
+        // The constants section is the third entry in the table
+        let mut constants = bytes.clone();
+        constants[HEADER_SIZE + 2 * SECTION_ENTRY_SIZE + 11] -= 1;
+        assert_eq!(
+            Executable::from_bytes(&constants),
+            Err(LoadError::MalformedConstants)
+        );
+
+        let mut symbols = bytes.clone();
+        let last = symbols.len() - 1;
+        symbols[last] = 0xff;
+        assert_eq!(
+            Executable::from_bytes(&symbols),
+            Err(LoadError::MalformedSymbols)
+        );
+    }
+}
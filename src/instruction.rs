@@ -1,5 +1,5 @@
 /// Opcodes for the [instruction set](https://gitlab.com/subnetzero/iridium/blob/master/docs/manual.adoc).
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Opcode {
     /// LOAD $0 $1 $2
     ///
@@ -19,7 +19,7 @@ pub enum Opcode {
     MUL = 3,
     /// DIV $0 $1 $2
     ///
-    /// Divides the contents of registers in operand 1 and 2; results go in register 3. The remainder goes in the remainder field of the VM.
+    /// Divides the contents of registers in operand 1 and 2; results go in register 3. The remainder goes in the remainder field of the VM, readable with MFR.
     DIV = 4,
     /// Halts execution of the program.
     HLT = 5,
@@ -29,11 +29,11 @@ pub enum Opcode {
     JMP = 6,
     /// JMPB $0
     ///
-    /// Relative jump backward by the number in the register.
+    /// Relative jump backward by the signed number of bytes in the register, counted from the next instruction.
     JMPB = 7,
-    /// JMPB $0
+    /// JMPF $0
     ///
-    /// Relative jump forward by the number in the register.
+    /// Relative jump forward by the signed number of bytes in the register, counted from the next instruction.
     JMPF = 8,
     /// EQ $0 $1
     ///
@@ -59,9 +59,9 @@ pub enum Opcode {
     ///
     /// Checks if register 1 is > register 2
     GT = 14,
-    /// JMPE $0 $1 $2
+    /// JMPE $0
     ///
-    /// Direct jump to the value in the register if the VM’s equal_flag is true
+    /// Direct jump to the value in the register if the VM’s E flag is true
     JMPE = 15,
     /// NOP
     ///
@@ -71,6 +71,246 @@ pub enum Opcode {
     ///
     /// Increases the heap by the amount specified in the first register
     ALOC = 17,
+    /// JZ $0
+    ///
+    /// Jumps to the address in the register if the Z flag is set
+    JZ = 18,
+    /// JNZ $0
+    ///
+    /// Jumps to the address in the register if the Z flag is clear
+    JNZ = 19,
+    /// JLT $0
+    ///
+    /// Jumps to the address in the register if the last result was signed less-than (N != V)
+    JLT = 20,
+    /// JGE $0
+    ///
+    /// Jumps to the address in the register if the last result was signed greater-or-equal (N == V)
+    JGE = 21,
+    /// JLE $0
+    ///
+    /// Jumps to the address in the register if Z is set or N != V
+    JLE = 22,
+    /// JGT $0
+    ///
+    /// Jumps to the address in the register if Z is clear and N == V
+    JGT = 23,
+    /// JC $0
+    ///
+    /// Jumps to the address in the register if the C flag is set
+    JC = 24,
+    /// JNC $0
+    ///
+    /// Jumps to the address in the register if the C flag is clear
+    JNC = 25,
+    /// JO $0
+    ///
+    /// Jumps to the address in the register if the V flag is set
+    JO = 26,
+    /// JNO $0
+    ///
+    /// Jumps to the address in the register if the V flag is clear
+    JNO = 27,
+    /// JMPNE $0
+    ///
+    /// Direct jump to the value in the register if the VM’s E flag is false
+    JMPNE = 28,
+    /// LOADB $0 $1 #2
+    ///
+    /// Loads the heap byte at the address in register 2 plus the 8-bit offset, zero-extended, into register 1
+    LOADB = 29,
+    /// LOADH $0 $1 #2
+    ///
+    /// Loads the big-endian half-word at the address in register 2 plus the offset, zero-extended, into register 1
+    LOADH = 30,
+    /// LOADW $0 $1 #2
+    ///
+    /// Loads the big-endian word at the address in register 2 plus the offset into register 1
+    LOADW = 31,
+    /// STOREB $0 $1 #2
+    ///
+    /// Stores the low byte of register 1 at the heap address in register 2 plus the offset
+    STOREB = 32,
+    /// STOREH $0 $1 #2
+    ///
+    /// Stores the low half-word of register 1, big-endian, at the heap address in register 2 plus the offset
+    STOREH = 33,
+    /// STOREW $0 $1 #2
+    ///
+    /// Stores register 1, big-endian, at the heap address in register 2 plus the offset
+    STOREW = 34,
+    /// CALL #0
+    ///
+    /// Pushes the address of the next instruction onto the call stack and jumps to the 16-bit address
+    CALL = 35,
+    /// CALLR $0
+    ///
+    /// Pushes the address of the next instruction onto the call stack and jumps to the address in the register
+    CALLR = 36,
+    /// RET
+    ///
+    /// Pops the call stack and jumps to the return address
+    RET = 37,
+    /// PUSH $0
+    ///
+    /// Pushes the register onto the data stack
+    PUSH = 38,
+    /// POP $0
+    ///
+    /// Pops the top of the data stack into the register
+    POP = 39,
+    /// PEEK $0 #1
+    ///
+    /// Copies the value the given number of slots below the top of the data stack into the register
+    PEEK = 40,
+    /// PUSHM $0 $1
+    ///
+    /// Pushes every register from the first to the last, inclusive, onto the data stack
+    PUSHM = 41,
+    /// POPM $0 $1
+    ///
+    /// Restores every register from the first to the last, inclusive, from values pushed by PUSHM
+    POPM = 42,
+    /// LOADF64 $f0 #1
+    ///
+    /// Loads the entry of the VM's float constant pool at the 16-bit index into the float register
+    LOADF64 = 43,
+    /// ADDF64 $f0 $f1 $f2
+    ///
+    /// Adds float registers 1 and 2 and places the result in float register 3
+    ADDF64 = 44,
+    /// SUBF64 $f0 $f1 $f2
+    ///
+    /// Subtracts float register 2 from float register 1 and places the result in float register 3
+    SUBF64 = 45,
+    /// MULF64 $f0 $f1 $f2
+    ///
+    /// Multiplies float registers 1 and 2 and places the result in float register 3
+    MULF64 = 46,
+    /// DIVF64 $f0 $f1 $f2
+    ///
+    /// Divides float register 1 by float register 2 and places the result in float register 3
+    DIVF64 = 47,
+    /// EQF64 $f0 $f1
+    ///
+    /// Sets the VM's E flag if float registers 1 and 2 are equal
+    EQF64 = 48,
+    /// NEQF64 $f0 $f1
+    ///
+    /// Sets the VM's E flag if float registers 1 and 2 are not equal
+    NEQF64 = 49,
+    /// GTF64 $f0 $f1
+    ///
+    /// Sets the VM's E flag if float register 1 is > float register 2
+    GTF64 = 50,
+    /// GTEF64 $f0 $f1
+    ///
+    /// Sets the VM's E flag if float register 1 is >= float register 2
+    GTEF64 = 51,
+    /// LTF64 $f0 $f1
+    ///
+    /// Sets the VM's E flag if float register 1 is < float register 2
+    LTF64 = 52,
+    /// LTEF64 $f0 $f1
+    ///
+    /// Sets the VM's E flag if float register 1 is <= float register 2
+    LTEF64 = 53,
+    /// ITOF $f0 $1
+    ///
+    /// Converts the integer register to a float and places it in the float register
+    ITOF = 54,
+    /// FTOI $0 $f1
+    ///
+    /// Truncates the float register to an integer, saturating at the i32 range, and places it in the register
+    FTOI = 55,
+    /// AND $0 $1 $2
+    ///
+    /// Bitwise AND of registers 1 and 2, placed in register 3
+    AND = 56,
+    /// OR $0 $1 $2
+    ///
+    /// Bitwise OR of registers 1 and 2, placed in register 3
+    OR = 57,
+    /// XOR $0 $1 $2
+    ///
+    /// Bitwise exclusive OR of registers 1 and 2, placed in register 3
+    XOR = 58,
+    /// NOT $0 $1
+    ///
+    /// Bitwise complement of register 1, placed in register 2
+    NOT = 59,
+    /// SHL $0 $1 $2
+    ///
+    /// Shifts register 1 left by the amount in register 2 and places the result in register 3. Amounts of 32 or more give 0.
+    SHL = 60,
+    /// SHR $0 $1 $2
+    ///
+    /// Logical right shift of register 1 by the amount in register 2, result in register 3. Amounts of 32 or more give 0.
+    SHR = 61,
+    /// SAR $0 $1 $2
+    ///
+    /// Arithmetic right shift of register 1 by the amount in register 2, result in register 3. Amounts of 32 or more give 0 or -1.
+    SAR = 62,
+    /// SHLI $0 $1 #2
+    ///
+    /// Shifts register 1 left by the 8-bit immediate and places the result in register 2
+    SHLI = 63,
+    /// SHRI $0 $1 #2
+    ///
+    /// Logical right shift of register 1 by the 8-bit immediate, result in register 2
+    SHRI = 64,
+    /// SARI $0 $1 #2
+    ///
+    /// Arithmetic right shift of register 1 by the 8-bit immediate, result in register 2
+    SARI = 65,
+    /// LUI $0 #1
+    ///
+    /// Loads the 16-bit immediate into the upper half of the register and clears the lower half
+    LUI = 66,
+    /// ORI $0 #1
+    ///
+    /// ORs the zero-extended 16-bit immediate into the register
+    ORI = 67,
+    /// ADDI $0 #1
+    ///
+    /// Adds the sign-extended 16-bit immediate to the register
+    ADDI = 68,
+    /// SUBI $0 #1
+    ///
+    /// Subtracts the sign-extended 16-bit immediate from the register
+    SUBI = 69,
+    /// MULI $0 #1
+    ///
+    /// Multiplies the register by the sign-extended 16-bit immediate
+    MULI = 70,
+    /// MOD $0 $1 $2
+    ///
+    /// Places the remainder of register 1 divided by register 2 in register 3. The result has the sign of register 1.
+    MOD = 71,
+    /// MFR $0
+    ///
+    /// Moves the remainder left by the last DIV into the register
+    MFR = 72,
+    /// DLOADB $0 $1 #2
+    ///
+    /// Loads the read-only data byte at the address in register 2 plus the 8-bit offset, zero-extended, into register 1
+    DLOADB = 73,
+    /// DLOADH $0 $1 #2
+    ///
+    /// Loads the big-endian read-only data half-word at the address in register 2 plus the offset, zero-extended, into register 1
+    DLOADH = 74,
+    /// DLOADW $0 $1 #2
+    ///
+    /// Loads the big-endian read-only data word at the address in register 2 plus the offset into register 1
+    DLOADW = 75,
+    /// MFSP $0
+    ///
+    /// Moves the data stack pointer, the number of values on the stack, into the register
+    MFSP = 76,
+    /// MTSP $0
+    ///
+    /// Sets the data stack pointer to the value in the register, dropping values or uncovering slots that keep whatever they last held
+    MTSP = 77,
     /// Used if an illegal opcode got in to the bytecode.
     IGL = 100,
 }
@@ -96,6 +336,66 @@ impl From<u8> for Opcode {
             15 => Opcode::JMPE,
             16 => Opcode::NOP,
             17 => Opcode::ALOC,
+            18 => Opcode::JZ,
+            19 => Opcode::JNZ,
+            20 => Opcode::JLT,
+            21 => Opcode::JGE,
+            22 => Opcode::JLE,
+            23 => Opcode::JGT,
+            24 => Opcode::JC,
+            25 => Opcode::JNC,
+            26 => Opcode::JO,
+            27 => Opcode::JNO,
+            28 => Opcode::JMPNE,
+            29 => Opcode::LOADB,
+            30 => Opcode::LOADH,
+            31 => Opcode::LOADW,
+            32 => Opcode::STOREB,
+            33 => Opcode::STOREH,
+            34 => Opcode::STOREW,
+            35 => Opcode::CALL,
+            36 => Opcode::CALLR,
+            37 => Opcode::RET,
+            38 => Opcode::PUSH,
+            39 => Opcode::POP,
+            40 => Opcode::PEEK,
+            41 => Opcode::PUSHM,
+            42 => Opcode::POPM,
+            43 => Opcode::LOADF64,
+            44 => Opcode::ADDF64,
+            45 => Opcode::SUBF64,
+            46 => Opcode::MULF64,
+            47 => Opcode::DIVF64,
+            48 => Opcode::EQF64,
+            49 => Opcode::NEQF64,
+            50 => Opcode::GTF64,
+            51 => Opcode::GTEF64,
+            52 => Opcode::LTF64,
+            53 => Opcode::LTEF64,
+            54 => Opcode::ITOF,
+            55 => Opcode::FTOI,
+            56 => Opcode::AND,
+            57 => Opcode::OR,
+            58 => Opcode::XOR,
+            59 => Opcode::NOT,
+            60 => Opcode::SHL,
+            61 => Opcode::SHR,
+            62 => Opcode::SAR,
+            63 => Opcode::SHLI,
+            64 => Opcode::SHRI,
+            65 => Opcode::SARI,
+            66 => Opcode::LUI,
+            67 => Opcode::ORI,
+            68 => Opcode::ADDI,
+            69 => Opcode::SUBI,
+            70 => Opcode::MULI,
+            71 => Opcode::MOD,
+            72 => Opcode::MFR,
+            73 => Opcode::DLOADB,
+            74 => Opcode::DLOADH,
+            75 => Opcode::DLOADW,
+            76 => Opcode::MFSP,
+            77 => Opcode::MTSP,
             _ => Opcode::IGL,
         }
     }
@@ -122,6 +422,66 @@ impl From<&str> for Opcode {
             "jmpe" => Opcode::JMPE,
             "nop" => Opcode::NOP,
             "aloc" => Opcode::ALOC,
+            "jz" => Opcode::JZ,
+            "jnz" => Opcode::JNZ,
+            "jlt" => Opcode::JLT,
+            "jge" => Opcode::JGE,
+            "jle" => Opcode::JLE,
+            "jgt" => Opcode::JGT,
+            "jc" => Opcode::JC,
+            "jnc" => Opcode::JNC,
+            "jo" => Opcode::JO,
+            "jno" => Opcode::JNO,
+            "jmpne" => Opcode::JMPNE,
+            "loadb" => Opcode::LOADB,
+            "loadh" => Opcode::LOADH,
+            "loadw" => Opcode::LOADW,
+            "storeb" => Opcode::STOREB,
+            "storeh" => Opcode::STOREH,
+            "storew" => Opcode::STOREW,
+            "call" => Opcode::CALL,
+            "callr" => Opcode::CALLR,
+            "ret" => Opcode::RET,
+            "push" => Opcode::PUSH,
+            "pop" => Opcode::POP,
+            "peek" => Opcode::PEEK,
+            "pushm" => Opcode::PUSHM,
+            "popm" => Opcode::POPM,
+            "loadf64" => Opcode::LOADF64,
+            "addf64" => Opcode::ADDF64,
+            "subf64" => Opcode::SUBF64,
+            "mulf64" => Opcode::MULF64,
+            "divf64" => Opcode::DIVF64,
+            "eqf64" => Opcode::EQF64,
+            "neqf64" => Opcode::NEQF64,
+            "gtf64" => Opcode::GTF64,
+            "gtef64" => Opcode::GTEF64,
+            "ltf64" => Opcode::LTF64,
+            "ltef64" => Opcode::LTEF64,
+            "itof" => Opcode::ITOF,
+            "ftoi" => Opcode::FTOI,
+            "and" => Opcode::AND,
+            "or" => Opcode::OR,
+            "xor" => Opcode::XOR,
+            "not" => Opcode::NOT,
+            "shl" => Opcode::SHL,
+            "shr" => Opcode::SHR,
+            "sar" => Opcode::SAR,
+            "shli" => Opcode::SHLI,
+            "shri" => Opcode::SHRI,
+            "sari" => Opcode::SARI,
+            "lui" => Opcode::LUI,
+            "ori" => Opcode::ORI,
+            "addi" => Opcode::ADDI,
+            "subi" => Opcode::SUBI,
+            "muli" => Opcode::MULI,
+            "mod" => Opcode::MOD,
+            "mfr" => Opcode::MFR,
+            "dloadb" => Opcode::DLOADB,
+            "dloadh" => Opcode::DLOADH,
+            "dloadw" => Opcode::DLOADW,
+            "mfsp" => Opcode::MFSP,
+            "mtsp" => Opcode::MTSP,
             _ => Opcode::IGL,
         }
     }
@@ -158,6 +518,8 @@ mod tests {
     fn test_str_to_opcode() {
         let opcode = Opcode::from("load");
         assert_eq!(opcode, Opcode::LOAD);
+        let opcode = Opcode::from("jnz");
+        assert_eq!(opcode, Opcode::JNZ);
         let opcode = Opcode::from("illegal");
         assert_eq!(opcode, Opcode::IGL);
     }
@@ -0,0 +1,6 @@
+pub mod abi;
+pub mod assembler;
+pub mod executable;
+pub mod instruction;
+pub mod repl;
+pub mod vm;
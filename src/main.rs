@@ -1,10 +1,4 @@
-#[macro_use]
-extern crate nom;
-
-pub mod assembler;
-pub mod instruction;
-pub mod repl;
-pub mod vm;
+use iridium::repl;
 
 fn main() {
     let mut repl = repl::REPL::new();
@@ -1,9 +1,15 @@
-use crate::vm::VM;
+use crate::vm::{ExitReason, RunStatus, VM};
 use std;
 use std::io;
 use std::io::Write;
 
-use crate::assembler::program_parsers::program;
+use crate::abi::register_name;
+use crate::assembler::disassembler::disassemble;
+use crate::assembler::Assembler;
+
+/// Most instructions a line of input may run, so that a jump back into earlier input cannot
+/// hang the REPL
+const MAX_INSTRUCTIONS_PER_LINE: usize = 10_000;
 
 /// Core structure for the REPL for the Assembler
 pub struct REPL {
@@ -65,27 +71,116 @@ impl REPL {
                     }
                     println!("End of Program Listing");
                 }
+                ".disassemble" => {
+                    println!("Disassembling VM's program vector:");
+                    for line in disassemble(&self.vm.program) {
+                        println!("{}", line);
+                    }
+                    println!("End of Disassembly");
+                }
                 ".registers" => {
                     println!("Listing registers and all contents:");
-                    println!("{:#?}", self.vm.registers);
+                    for (index, value) in self.vm.registers.iter().enumerate() {
+                        let name = register_name(index as u8).unwrap_or_default();
+                        println!("${} (${}): {}", index, name, value);
+                    }
+                    println!("remainder: {}", self.vm.remainder());
                     println!("End of Register Listing");
                 }
-                _ => {
-                    let parsed_program = program(buffer);
-
-                    if parsed_program.is_err() {
-                        println!("Unable to parse input");
-                        continue;
+                ".float_registers" => {
+                    println!("Listing float registers and all contents:");
+                    for (index, value) in self.vm.float_registers.iter().enumerate() {
+                        println!("$f{}: {}", index, value);
                     }
-
-                    let (_, result) = parsed_program.unwrap();
-                    let bytecode = result.to_bytes();
-                    for byte in bytecode {
-                        self.vm.add_byte(byte);
+                    println!("End of Float Register Listing");
+                }
+                ".heap" => {
+                    println!("Listing heap contents:");
+                    for (offset, chunk) in self.vm.heap().chunks(16).enumerate() {
+                        let bytes: Vec<String> =
+                            chunk.iter().map(|b| format!("{:02x}", b)).collect();
+                        println!("{:08x}: {}", offset * 16, bytes.join(" "));
                     }
-                    self.vm.run_once();
+                    println!("End of Heap Listing");
+                }
+                ".stack" => {
+                    println!("Listing data stack, top first (sp = {}):", self.vm.sp());
+                    for value in self.vm.stack().iter().rev() {
+                        println!("{}", value);
+                    }
+                    println!("End of Stack Listing");
+                }
+                ".callstack" => {
+                    println!("Listing call stack, innermost frame first:");
+                    for (depth, frame) in self.vm.call_stack().iter().rev().enumerate() {
+                        println!("#{} {}", depth, frame);
+                    }
+                    println!("End of Call Stack Listing");
+                }
+                ".flags" => {
+                    println!("{}", self.vm.flags());
+                }
+                _ => self.execute_line(buffer),
+            }
+        }
+    }
+
+    /// Assembles a line of input, appends it to the VM's program and runs it
+    fn execute_line(&mut self, line: &str) {
+        // Each line is assembled on its own, so there are no labels to refer to
+        let bytecode = match Assembler::new().assemble_source(line, "<repl>") {
+            Ok(program) => program.code,
+            Err(errors) => {
+                for error in errors {
+                    println!("{}", error);
                 }
+                return;
+            }
+        };
+        for byte in bytecode {
+            self.vm.add_byte(byte);
+        }
+        // A line can assemble to several instructions, such as a wide `load`, so run until the
+        // new instructions are used up
+        match self.vm.run_for(MAX_INSTRUCTIONS_PER_LINE) {
+            RunStatus::Halted(ExitReason::Halted) => println!("HLT encountered"),
+            RunStatus::Halted(_) => {}
+            RunStatus::Yielded => {
+                println!(
+                    "Instruction budget of {} exhausted, skipping to the end of the program",
+                    MAX_INSTRUCTIONS_PER_LINE
+                );
+                self.vm.skip_to_end();
+            }
+            RunStatus::Faulted(e) => {
+                println!("VM fault: {}", e);
+                // Skip the rest of the faulting instruction so that the next line runs
+                self.vm.skip_to_end();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_after_fault_runs() {
+        let mut repl = REPL::new();
+        repl.execute_line("load $0 #9");
+        repl.execute_line("div $0 $1 $2");
+        repl.execute_line("load $3 #7");
+        assert_eq!(repl.vm.registers[0], 9);
+        assert_eq!(repl.vm.registers[3], 7);
+    }
+
+    #[test]
+    fn test_backward_jump_is_bounded() {
+        let mut repl = REPL::new();
+        repl.execute_line("load $0 #0");
+        repl.execute_line("jmp $0");
+        repl.execute_line("load $1 #5");
+        assert_eq!(repl.vm.registers[1], 5);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::vm::VmErrorKind;
+
+/// How integer arithmetic opcodes behave when the result does not fit in an i32
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ArithmeticMode {
+    /// Results wrap around in two's complement
+    #[default]
+    Wrapping,
+    /// Results are clamped to `i32::MIN` / `i32::MAX`
+    Saturating,
+    /// Overflow faults the VM
+    Trapping,
+}
+
+impl ArithmeticMode {
+    pub fn add(self, a: i32, b: i32) -> Result<i32, VmErrorKind> {
+        self.resolve(a.overflowing_add(b), || a.saturating_add(b))
+    }
+
+    pub fn sub(self, a: i32, b: i32) -> Result<i32, VmErrorKind> {
+        self.resolve(a.overflowing_sub(b), || a.saturating_sub(b))
+    }
+
+    pub fn mul(self, a: i32, b: i32) -> Result<i32, VmErrorKind> {
+        self.resolve(a.overflowing_mul(b), || a.saturating_mul(b))
+    }
+
+    /// Divides `a` by `b`. The caller must check for a zero divisor first.
+    pub fn div(self, a: i32, b: i32) -> Result<i32, VmErrorKind> {
+        self.resolve(a.overflowing_div(b), || a.saturating_div(b))
+    }
+
+    fn resolve(
+        self,
+        (wrapped, overflowed): (i32, bool),
+        saturated: impl FnOnce() -> i32,
+    ) -> Result<i32, VmErrorKind> {
+        if !overflowed {
+            return Ok(wrapped);
+        }
+        match self {
+            ArithmeticMode::Wrapping => Ok(wrapped),
+            ArithmeticMode::Saturating => Ok(saturated()),
+            ArithmeticMode::Trapping => Err(VmErrorKind::ArithmeticOverflow),
+        }
+    }
+}
+
+/// Logical left shift; amounts of 32 or more shift every bit out
+pub fn shl(value: i32, amount: u32) -> i32 {
+    value.checked_shl(amount).unwrap_or(0)
+}
+
+/// Logical right shift; amounts of 32 or more shift every bit out
+pub fn shr(value: i32, amount: u32) -> i32 {
+    (value as u32).checked_shr(amount).unwrap_or(0) as i32
+}
+
+/// Arithmetic right shift; amounts of 32 or more leave only copies of the sign bit
+pub fn sar(value: i32, amount: u32) -> i32 {
+    value >> amount.min(31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapping() {
+        let mode = ArithmeticMode::Wrapping;
+        assert_eq!(mode.add(i32::MAX, 1), Ok(i32::MIN));
+        assert_eq!(mode.sub(i32::MIN, 1), Ok(i32::MAX));
+        assert_eq!(mode.div(i32::MIN, -1), Ok(i32::MIN));
+    }
+
+    #[test]
+    fn test_saturating() {
+        let mode = ArithmeticMode::Saturating;
+        assert_eq!(mode.add(i32::MAX, 1), Ok(i32::MAX));
+        assert_eq!(mode.mul(i32::MIN, 2), Ok(i32::MIN));
+        assert_eq!(mode.div(i32::MIN, -1), Ok(i32::MAX));
+    }
+
+    #[test]
+    fn test_trapping() {
+        let mode = ArithmeticMode::Trapping;
+        assert_eq!(mode.add(1, 2), Ok(3));
+        assert_eq!(mode.add(i32::MAX, 1), Err(VmErrorKind::ArithmeticOverflow));
+        assert_eq!(
+            mode.mul(1 << 16, 1 << 16),
+            Err(VmErrorKind::ArithmeticOverflow)
+        );
+        assert_eq!(mode.div(i32::MIN, -1), Err(VmErrorKind::ArithmeticOverflow));
+    }
+
+    #[test]
+    fn test_shifts() {
+        assert_eq!(shl(1, 4), 16);
+        assert_eq!(shl(1, 31), i32::MIN);
+        assert_eq!(shl(1, 32), 0);
+        assert_eq!(shr(-16, 28), 0xf);
+        assert_eq!(shr(-1, 40), 0);
+        assert_eq!(sar(-16, 2), -4);
+        assert_eq!(sar(-16, 32), -1);
+        assert_eq!(sar(16, u32::MAX), 0);
+    }
+}
@@ -0,0 +1,130 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why a VM stopped executing without faulting
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExitReason {
+    /// A HLT instruction was executed
+    Halted,
+    /// The program counter reached the end of the program
+    EndOfProgram,
+    /// A single instruction was executed and the program can continue
+    Yielded,
+}
+
+/// Outcome of running a bounded number of instructions with `VM::run_for`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RunStatus {
+    /// The instruction budget ran out; calling `run_for` again resumes where execution stopped
+    Yielded,
+    /// The program finished, either with `ExitReason::Halted` or `ExitReason::EndOfProgram`
+    Halted(ExitReason),
+    /// The program faulted
+    Faulted(VmError),
+}
+
+/// The kind of fault that stopped the VM
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VmErrorKind {
+    /// The opcode byte does not map to any known instruction
+    IllegalOpcode,
+    /// A jump tried to move the program counter past the end of the program
+    PcOutOfBounds { target: i64 },
+    /// A jump target was not on an instruction boundary
+    MisalignedJump { target: i64 },
+    /// The program ended in the middle of an instruction's operands
+    TruncatedInstruction,
+    /// An operand referred to a register that does not exist
+    InvalidRegister { index: u8 },
+    /// LOADF64 referred to a constant pool entry that does not exist
+    InvalidConstant { index: u16 },
+    /// DIV was executed with a zero divisor
+    DivideByZero,
+    /// An arithmetic result did not fit in an i32 while trapping on overflow
+    ArithmeticOverflow,
+    /// CALL would have nested deeper than the VM's maximum call depth
+    CallStackOverflow { depth: usize },
+    /// RET was executed with no frame on the call stack
+    CallStackUnderflow,
+    /// PUSH would have grown the data stack past its configured size
+    StackOverflow { size: usize },
+    /// POP or PEEK reached below the bottom of the data stack
+    StackUnderflow,
+    /// A metered VM did not have enough fuel left to pay for the instruction
+    OutOfFuel { cost: u64, remaining: u64 },
+    /// A heap operation went outside of the heap
+    HeapOutOfBounds { address: i64, len: usize },
+    /// A read-only data load went outside of the data area
+    DataOutOfBounds { address: i64, len: usize },
+    /// ALOC tried to grow the heap beyond the VM's configured limit
+    HeapLimitExceeded { requested: usize, limit: usize },
+}
+
+/// A fault raised while executing an instruction
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VmError {
+    pub kind: VmErrorKind,
+    /// Address of the first byte of the faulting instruction
+    pub pc: usize,
+    /// The opcode byte of the faulting instruction
+    pub opcode: u8,
+}
+
+impl fmt::Display for VmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmErrorKind::IllegalOpcode => write!(f, "illegal opcode"),
+            VmErrorKind::PcOutOfBounds { target } => {
+                write!(f, "jump target {} is past the end of the program", target)
+            }
+            VmErrorKind::MisalignedJump { target } => {
+                write!(f, "jump target {} is not instruction aligned", target)
+            }
+            VmErrorKind::TruncatedInstruction => write!(f, "truncated instruction"),
+            VmErrorKind::InvalidRegister { index } => write!(f, "invalid register ${}", index),
+            VmErrorKind::InvalidConstant { index } => {
+                write!(f, "constant pool has no entry {}", index)
+            }
+            VmErrorKind::DivideByZero => write!(f, "divide by zero"),
+            VmErrorKind::ArithmeticOverflow => write!(f, "integer overflow"),
+            VmErrorKind::CallStackOverflow { depth } => {
+                write!(f, "stack overflow (call depth {})", depth)
+            }
+            VmErrorKind::CallStackUnderflow => write!(f, "return with an empty call stack"),
+            VmErrorKind::StackOverflow { size } => {
+                write!(f, "data stack overflow ({} slots)", size)
+            }
+            VmErrorKind::StackUnderflow => write!(f, "data stack underflow"),
+            VmErrorKind::OutOfFuel { cost, remaining } => {
+                write!(f, "out of fuel (needs {}, {} left)", cost, remaining)
+            }
+            VmErrorKind::HeapOutOfBounds { address, len } => write!(
+                f,
+                "heap access at {} is out of bounds (heap is {} bytes)",
+                address, len
+            ),
+            VmErrorKind::DataOutOfBounds { address, len } => write!(
+                f,
+                "data access at {} is out of bounds (data area is {} bytes)",
+                address, len
+            ),
+            VmErrorKind::HeapLimitExceeded { requested, limit } => write!(
+                f,
+                "heap of {} bytes exceeds the limit of {} bytes",
+                requested, limit
+            ),
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at pc {} (opcode {})",
+            self.kind, self.pc, self.opcode
+        )
+    }
+}
+
+impl Error for VmError {}
@@ -0,0 +1,110 @@
+use std::fmt;
+
+/// Status flags set by the arithmetic and comparison opcodes
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Flags {
+    /// Z: the last result was zero
+    pub zero: bool,
+    /// N: the last result was negative
+    pub negative: bool,
+    /// C: the last operation carried out of (or, for subtraction, borrowed into) bit 31
+    pub carry: bool,
+    /// V: the last operation overflowed as a signed operation
+    pub overflow: bool,
+    /// E: the outcome of the last comparison opcode, tested by JMPE
+    pub equal: bool,
+}
+
+impl Flags {
+    /// Updates Z/N/C/V after computing `a + b`
+    pub fn set_add(&mut self, a: i32, b: i32, result: i32) {
+        self.set_result(result);
+        self.carry = (a as u32).overflowing_add(b as u32).1;
+        self.overflow = a.overflowing_add(b).1;
+    }
+
+    /// Updates Z/N/C/V after computing `a - b`
+    pub fn set_sub(&mut self, a: i32, b: i32, result: i32) {
+        self.set_result(result);
+        self.carry = (a as u32) < (b as u32);
+        self.overflow = a.overflowing_sub(b).1;
+    }
+
+    /// Updates Z/N/C/V after computing `a * b`. C and V are both set if the product overflowed.
+    pub fn set_mul(&mut self, a: i32, b: i32, result: i32) {
+        self.set_result(result);
+        self.overflow = a.overflowing_mul(b).1;
+        self.carry = self.overflow;
+    }
+
+    /// Updates Z/N/C/V after computing `a / b`
+    pub fn set_div(&mut self, a: i32, b: i32, result: i32) {
+        self.set_result(result);
+        self.carry = false;
+        self.overflow = a.overflowing_div(b).1;
+    }
+
+    /// Updates Z/N after computing a remainder, which can never carry or overflow
+    pub fn set_rem(&mut self, result: i32) {
+        self.set_logic(result);
+    }
+
+    /// Updates Z/N after a bitwise or shift operation and clears C/V
+    pub fn set_logic(&mut self, result: i32) {
+        self.set_result(result);
+        self.carry = false;
+        self.overflow = false;
+    }
+
+    /// Signed less-than after a subtraction or comparison
+    pub fn less(&self) -> bool {
+        self.negative != self.overflow
+    }
+
+    fn set_result(&mut self, result: i32) {
+        self.zero = result == 0;
+        self.negative = result < 0;
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Z={} N={} C={} V={} E={}",
+            self.zero as u8,
+            self.negative as u8,
+            self.carry as u8,
+            self.overflow as u8,
+            self.equal as u8
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sub_flags() {
+        let mut flags = Flags::default();
+        flags.set_sub(5, 5, 0);
+        assert!(flags.zero && !flags.negative && !flags.carry && !flags.overflow);
+
+        flags.set_sub(3, 5, -2);
+        assert!(flags.negative && flags.carry && flags.less());
+
+        flags.set_sub(i32::MIN, 1, i32::MAX);
+        assert!(flags.overflow && !flags.negative && flags.less());
+    }
+
+    #[test]
+    fn test_add_flags() {
+        let mut flags = Flags::default();
+        flags.set_add(-1, 1, 0);
+        assert!(flags.zero && flags.carry && !flags.overflow);
+
+        flags.set_add(i32::MAX, 1, i32::MIN);
+        assert!(flags.negative && !flags.carry && flags.overflow);
+    }
+}
@@ -0,0 +1,22 @@
+use std::fmt;
+
+/// A call stack entry pushed by CALL and popped by RET
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// Address of the CALL instruction that created the frame
+    pub call_site: usize,
+    /// Address of the subroutine that was called
+    pub entry: usize,
+    /// Address execution resumes at after RET
+    pub return_address: usize,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "subroutine at {} called from {}",
+            self.entry, self.call_site
+        )
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use crate::instruction::Opcode;
+
+/// Fuel charged for executing each opcode
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostTable {
+    /// Cost of any opcode without an explicit entry
+    default_cost: u64,
+    costs: HashMap<Opcode, u64>,
+}
+
+impl CostTable {
+    /// Creates a table that charges `default_cost` for every opcode
+    pub fn new(default_cost: u64) -> CostTable {
+        CostTable {
+            default_cost,
+            costs: HashMap::new(),
+        }
+    }
+
+    /// Overrides the cost of a single opcode
+    pub fn with_cost(mut self, opcode: Opcode, cost: u64) -> CostTable {
+        self.costs.insert(opcode, cost);
+        self
+    }
+
+    /// Returns the fuel charged for executing `opcode`
+    pub fn cost(&self, opcode: Opcode) -> u64 {
+        self.costs
+            .get(&opcode)
+            .copied()
+            .unwrap_or(self.default_cost)
+    }
+}
+
+impl Default for CostTable {
+    /// Charges one unit of fuel per instruction
+    fn default() -> Self {
+        CostTable::new(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_table() {
+        let table = CostTable::new(2)
+            .with_cost(Opcode::ALOC, 50)
+            .with_cost(Opcode::DIV, 8);
+        assert_eq!(table.cost(Opcode::ALOC), 50);
+        assert_eq!(table.cost(Opcode::DIV), 8);
+        assert_eq!(table.cost(Opcode::ADD), 2);
+        assert_eq!(CostTable::default().cost(Opcode::ALOC), 1);
+    }
+}
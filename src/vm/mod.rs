@@ -0,0 +1,2099 @@
+use crate::executable::{Executable, LoadError};
+use crate::instruction::Opcode;
+
+pub mod arithmetic;
+pub mod error;
+pub mod flags;
+pub mod frame;
+pub mod fuel;
+
+pub use self::arithmetic::ArithmeticMode;
+pub use self::error::{ExitReason, RunStatus, VmError, VmErrorKind};
+pub use self::flags::Flags;
+pub use self::frame::Frame;
+pub use self::fuel::CostTable;
+
+/// Every instruction is encoded as an opcode byte followed by three operand bytes
+pub const INSTRUCTION_SIZE: usize = 4;
+
+/// Number of integer registers, and of float registers
+pub const REGISTER_COUNT: usize = 32;
+
+/// Largest heap a program may grow to unless configured otherwise
+pub const DEFAULT_HEAP_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Number of 32-bit slots in the data stack unless configured otherwise
+pub const DEFAULT_STACK_SIZE: usize = 4096;
+
+/// Deepest the call stack may grow unless configured otherwise
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+
+pub struct VM {
+    /// Array that simulates having hardware registers
+    pub registers: [i32; REGISTER_COUNT],
+    /// Separate bank of floating-point registers, addressed as `$f0`..`$f31`
+    pub float_registers: [f64; REGISTER_COUNT],
+    /// Program counter that tracks which byte is being executed
+    pc: usize,
+    /// The bytecode of the program being run
+    pub program: Vec<u8>,
+    /// Constant pool read by LOADF64
+    pub float_constants: Vec<f64>,
+    /// Read-only data area, filled from an assembled program's data section and read by
+    /// DLOADB, DLOADH and DLOADW
+    pub ro_data: Vec<u8>,
+
+    /// Used for heap memory
+    heap: Vec<u8>,
+    /// Maximum size in bytes `ALOC` may grow the heap to
+    heap_limit: usize,
+
+    /// Data stack region used by PUSH, POP and friends
+    stack: Vec<i32>,
+    /// Stack pointer: index of the next free slot in `stack`, read by MFSP and set by MTSP
+    sp: usize,
+
+    /// Return addresses of the subroutines currently executing, innermost last
+    call_stack: Vec<Frame>,
+    /// Maximum number of frames on the call stack
+    max_call_depth: usize,
+
+    /// Fuel left to spend, or `None` if execution is not metered
+    fuel: Option<u64>,
+    /// Fuel spent since the last call to `run` or `run_for`
+    fuel_consumed: u64,
+    /// Fuel charged per opcode
+    cost_table: CostTable,
+
+    /// Overflow behaviour of the integer arithmetic opcodes
+    arithmetic_mode: ArithmeticMode,
+
+    /// Contains the remainder of the last DIV, with the sign of the dividend
+    remainder: i32,
+    /// Status flags set by arithmetic and comparison operations
+    flags: Flags,
+}
+
+impl VM {
+    pub fn new() -> VM {
+        VM {
+            registers: [0; REGISTER_COUNT],
+            float_registers: [0.0; REGISTER_COUNT],
+            program: vec![],
+            float_constants: vec![],
+            ro_data: vec![],
+            pc: 0,
+            heap: vec![],
+            heap_limit: DEFAULT_HEAP_LIMIT,
+            stack: vec![0; DEFAULT_STACK_SIZE],
+            sp: 0,
+            call_stack: vec![],
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fuel: None,
+            fuel_consumed: 0,
+            cost_table: CostTable::default(),
+            arithmetic_mode: ArithmeticMode::default(),
+            remainder: 0,
+            flags: Flags::default(),
+        }
+    }
+
+    /// Sets the maximum size in bytes the heap may grow to
+    pub fn with_heap_limit(mut self, limit: usize) -> VM {
+        self.heap_limit = limit;
+        self
+    }
+
+    /// Returns the contents of the heap
+    pub fn heap(&self) -> &[u8] {
+        &self.heap
+    }
+
+    /// Returns the remainder left by the last DIV
+    pub fn remainder(&self) -> i32 {
+        self.remainder
+    }
+
+    /// Returns the current status flags
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    /// Sets how ADD, SUB, MUL and DIV handle overflow
+    pub fn with_arithmetic_mode(mut self, mode: ArithmeticMode) -> VM {
+        self.arithmetic_mode = mode;
+        self
+    }
+
+    /// Sets the maximum number of nested CALLs before the VM faults with a stack overflow
+    pub fn with_max_call_depth(mut self, depth: usize) -> VM {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// Sets the number of 32-bit slots in the data stack
+    pub fn with_stack_size(mut self, size: usize) -> VM {
+        self.stack = vec![0; size];
+        self.sp = 0;
+        self
+    }
+
+    /// Returns the current value of the stack pointer
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// Returns the values currently on the data stack, bottom first
+    pub fn stack(&self) -> &[i32] {
+        &self.stack[..self.sp]
+    }
+
+    /// Returns the active call frames, outermost first
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
+    /// Meters execution: the VM faults with "out of fuel" once `fuel` is spent
+    pub fn with_fuel(mut self, fuel: u64) -> VM {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Sets the fuel charged for each opcode
+    pub fn with_cost_table(mut self, cost_table: CostTable) -> VM {
+        self.cost_table = cost_table;
+        self
+    }
+
+    /// Adds fuel to a metered VM, for example to resume after it ran out
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(remaining) = self.fuel.as_mut() {
+            *remaining = remaining.saturating_add(fuel);
+        }
+    }
+
+    /// Returns the fuel left, or `None` if execution is not metered
+    pub fn fuel_remaining(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Returns the fuel spent by the most recent `run` or `run_for`, plus any `run_once` calls
+    /// since. The count saturates at `u64::MAX`.
+    pub fn fuel_consumed(&self) -> u64 {
+        self.fuel_consumed
+    }
+
+    /// Runs until the program halts, runs off its end or faults
+    pub fn run(&mut self) -> Result<ExitReason, VmError> {
+        self.fuel_consumed = 0;
+        loop {
+            match self.run_once()? {
+                ExitReason::Yielded => continue,
+                reason => return Ok(reason),
+            }
+        }
+    }
+
+    /// Executes at most `n_instructions` instructions. A yielded VM can be resumed by calling
+    /// `run_for` or `run` again.
+    pub fn run_for(&mut self, n_instructions: usize) -> RunStatus {
+        self.fuel_consumed = 0;
+        for _ in 0..n_instructions {
+            match self.run_once() {
+                Ok(ExitReason::Yielded) => continue,
+                Ok(reason) => return RunStatus::Halted(reason),
+                Err(e) => return RunStatus::Faulted(e),
+            }
+        }
+        RunStatus::Yielded
+    }
+
+    /// Executes a single instruction. Returns `ExitReason::Yielded` if the program can continue.
+    pub fn run_once(&mut self) -> Result<ExitReason, VmError> {
+        // 如果 pc(程序计数器) 超出 program 的长度，则结束
+        if self.pc >= self.program.len() {
+            return Ok(ExitReason::EndOfProgram);
+        }
+
+        let pc = self.pc;
+        let opcode = self.program[pc];
+        self.charge_fuel(Opcode::from(opcode))
+            .and_then(|_| self.execute_instruction())
+            .map_err(|kind| VmError { kind, pc, opcode })
+    }
+
+    /// Loads an executable file's code, read-only data and float constants and moves the program
+    /// counter to its entry point. Everything else the previous program left behind is cleared:
+    /// registers, flags, the heap and both stacks. Configured limits, the fuel budget, the cost
+    /// table and the arithmetic mode are kept. The file is rejected if its header is invalid or
+    /// it was built for another ISA version, in which case the VM is left unchanged.
+    pub fn load_executable(&mut self, bytes: &[u8]) -> Result<(), LoadError> {
+        let executable = Executable::from_bytes(bytes)?;
+        *self = VM {
+            program: executable.code,
+            ro_data: executable.data,
+            float_constants: executable.float_constants,
+            pc: executable.entry as usize,
+            heap_limit: self.heap_limit,
+            stack: vec![0; self.stack.len()],
+            max_call_depth: self.max_call_depth,
+            fuel: self.fuel,
+            cost_table: std::mem::take(&mut self.cost_table),
+            arithmetic_mode: self.arithmetic_mode,
+            ..VM::new()
+        };
+        Ok(())
+    }
+
+    /// Adds an arbitrary byte to the VM's program
+    pub fn add_byte(&mut self, b: u8) {
+        self.program.push(b);
+    }
+
+    /// Adds arbitrary bytes list to the VM's program
+    pub fn add_bytes(&mut self, mut b: Vec<u8>) {
+        self.program.append(&mut b);
+    }
+
+    /// Moves the program counter to the end of the program, so that the next bytes added are
+    /// the next to run. A fault leaves the program counter partway through the faulting
+    /// instruction, so the REPL calls this to carry on after one.
+    pub fn skip_to_end(&mut self) {
+        self.pc = self.program.len();
+    }
+
+    fn execute_instruction(&mut self) -> Result<ExitReason, VmErrorKind> {
+        match self.decode_opcode() {
+            Opcode::LOAD => {
+                let register = self.next_register()?;
+                let number = self.next_16_bits()?;
+                self.registers[register] = number as i32;
+            }
+            Opcode::ADD => {
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+
+                let result = self.arithmetic_mode.add(register1, register2)?;
+                self.flags.set_add(register1, register2, result);
+                self.registers[self.next_register()?] = result;
+            }
+            Opcode::SUB => {
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+
+                let result = self.arithmetic_mode.sub(register1, register2)?;
+                self.flags.set_sub(register1, register2, result);
+                self.registers[self.next_register()?] = result;
+            }
+            Opcode::MUL => {
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+
+                let result = self.arithmetic_mode.mul(register1, register2)?;
+                self.flags.set_mul(register1, register2, result);
+                self.registers[self.next_register()?] = result;
+            }
+            Opcode::DIV => {
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+                if register2 == 0 {
+                    return Err(VmErrorKind::DivideByZero);
+                }
+
+                let result = self.arithmetic_mode.div(register1, register2)?;
+                self.flags.set_div(register1, register2, result);
+                self.registers[self.next_register()?] = result;
+
+                self.remainder = register1.wrapping_rem(register2);
+            }
+            Opcode::JMP => {
+                let target = self.jump_operand()?;
+                self.jump_to(target as i64)?;
+            }
+            Opcode::JMPF => {
+                let offset = self.jump_operand()?;
+                self.jump_to(self.pc as i64 + offset as i64)?;
+            }
+            Opcode::JMPB => {
+                let offset = self.jump_operand()?;
+                self.jump_to(self.pc as i64 - offset as i64)?;
+            }
+            Opcode::EQ => {
+                let (register1, register2) = self.compare()?;
+                self.flags.equal = register1 == register2;
+            }
+            Opcode::NEQ => {
+                let (register1, register2) = self.compare()?;
+                self.flags.equal = register1 != register2;
+            }
+            Opcode::GT => {
+                let (register1, register2) = self.compare()?;
+                self.flags.equal = register1 > register2;
+            }
+            Opcode::GTE => {
+                let (register1, register2) = self.compare()?;
+                self.flags.equal = register1 >= register2;
+            }
+            Opcode::LT => {
+                let (register1, register2) = self.compare()?;
+                self.flags.equal = register1 < register2;
+            }
+            Opcode::LTE => {
+                let (register1, register2) = self.compare()?;
+                self.flags.equal = register1 <= register2;
+            }
+            Opcode::JMPE => {
+                let target = self.jump_operand()?;
+                if self.flags.equal {
+                    self.jump_to(target as i64)?;
+                }
+            }
+            Opcode::JMPNE => {
+                let target = self.jump_operand()?;
+                if !self.flags.equal {
+                    self.jump_to(target as i64)?;
+                }
+            }
+            opcode @ (Opcode::JZ
+            | Opcode::JNZ
+            | Opcode::JLT
+            | Opcode::JGE
+            | Opcode::JLE
+            | Opcode::JGT
+            | Opcode::JC
+            | Opcode::JNC
+            | Opcode::JO
+            | Opcode::JNO) => {
+                let target = self.jump_operand()?;
+                let flags = self.flags;
+                let taken = match opcode {
+                    Opcode::JZ => flags.zero,
+                    Opcode::JNZ => !flags.zero,
+                    Opcode::JLT => flags.less(),
+                    Opcode::JGE => !flags.less(),
+                    Opcode::JLE => flags.zero || flags.less(),
+                    Opcode::JGT => !flags.zero && !flags.less(),
+                    Opcode::JC => flags.carry,
+                    Opcode::JNC => !flags.carry,
+                    Opcode::JO => flags.overflow,
+                    _ => !flags.overflow,
+                };
+                if taken {
+                    self.jump_to(target as i64)?;
+                }
+            }
+            Opcode::HLT => {
+                // Step over the padding so execution can resume on the next instruction
+                self.pc = (self.pc + INSTRUCTION_SIZE - 1).min(self.program.len());
+                return Ok(ExitReason::Halted);
+            }
+            Opcode::NOP => {
+                self.next_8_bits()?;
+                self.next_8_bits()?;
+                self.next_8_bits()?;
+            }
+            Opcode::ALOC => {
+                let bytes = self.registers[self.next_register()?];
+                self.next_16_bits()?;
+                let new_end = self.heap.len() as i64 + bytes as i64;
+                if new_end < 0 {
+                    return Err(VmErrorKind::HeapOutOfBounds {
+                        address: new_end,
+                        len: self.heap.len(),
+                    });
+                }
+                if new_end as usize > self.heap_limit {
+                    return Err(VmErrorKind::HeapLimitExceeded {
+                        requested: new_end as usize,
+                        limit: self.heap_limit,
+                    });
+                }
+                self.heap.resize(new_end as usize, 0)
+            }
+            Opcode::LOADB => {
+                let register = self.next_register()?;
+                let address = self.next_heap_address(1)?;
+                self.registers[register] = self.heap[address] as i32;
+            }
+            Opcode::LOADH => {
+                let register = self.next_register()?;
+                let address = self.next_heap_address(2)?;
+                let bytes = [self.heap[address], self.heap[address + 1]];
+                self.registers[register] = u16::from_be_bytes(bytes) as i32;
+            }
+            Opcode::LOADW => {
+                let register = self.next_register()?;
+                let address = self.next_heap_address(4)?;
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&self.heap[address..address + 4]);
+                self.registers[register] = i32::from_be_bytes(bytes);
+            }
+            Opcode::DLOADB => {
+                let register = self.next_register()?;
+                let address = self.next_data_address(1)?;
+                self.registers[register] = self.ro_data[address] as i32;
+            }
+            Opcode::DLOADH => {
+                let register = self.next_register()?;
+                let address = self.next_data_address(2)?;
+                let bytes = [self.ro_data[address], self.ro_data[address + 1]];
+                self.registers[register] = u16::from_be_bytes(bytes) as i32;
+            }
+            Opcode::DLOADW => {
+                let register = self.next_register()?;
+                let address = self.next_data_address(4)?;
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&self.ro_data[address..address + 4]);
+                self.registers[register] = i32::from_be_bytes(bytes);
+            }
+            Opcode::STOREB => {
+                let value = self.registers[self.next_register()?];
+                let address = self.next_heap_address(1)?;
+                self.heap[address] = value as u8;
+            }
+            Opcode::STOREH => {
+                let value = self.registers[self.next_register()?];
+                let address = self.next_heap_address(2)?;
+                self.heap[address..address + 2].copy_from_slice(&(value as u16).to_be_bytes());
+            }
+            Opcode::STOREW => {
+                let value = self.registers[self.next_register()?];
+                let address = self.next_heap_address(4)?;
+                self.heap[address..address + 4].copy_from_slice(&value.to_be_bytes());
+            }
+            Opcode::CALL => {
+                let target = self.next_16_bits()?;
+                self.next_8_bits()?;
+                self.call(target as i64)?;
+            }
+            Opcode::CALLR => {
+                let target = self.jump_operand()?;
+                self.call(target as i64)?;
+            }
+            Opcode::RET => {
+                self.next_8_bits()?;
+                self.next_16_bits()?;
+                let frame = self
+                    .call_stack
+                    .pop()
+                    .ok_or(VmErrorKind::CallStackUnderflow)?;
+                self.pc = frame.return_address;
+            }
+            Opcode::PUSH => {
+                let value = self.registers[self.next_register()?];
+                self.next_16_bits()?;
+                self.reserve_stack(1)?;
+                self.stack[self.sp] = value;
+                self.sp += 1;
+            }
+            Opcode::POP => {
+                let register = self.next_register()?;
+                self.next_16_bits()?;
+                self.release_stack(1)?;
+                self.registers[register] = self.stack[self.sp];
+            }
+            Opcode::PEEK => {
+                let register = self.next_register()?;
+                let depth = self.next_16_bits()? as usize;
+                if depth >= self.sp {
+                    return Err(VmErrorKind::StackUnderflow);
+                }
+                self.registers[register] = self.stack[self.sp - 1 - depth];
+            }
+            Opcode::PUSHM => {
+                let (first, last) = self.register_range()?;
+                self.reserve_stack(last + 1 - first)?;
+                for register in first..=last {
+                    self.stack[self.sp] = self.registers[register];
+                    self.sp += 1;
+                }
+            }
+            Opcode::POPM => {
+                let (first, last) = self.register_range()?;
+                self.release_stack(last + 1 - first)?;
+                for (offset, register) in (first..=last).enumerate() {
+                    self.registers[register] = self.stack[self.sp + offset];
+                }
+            }
+            Opcode::MFSP => {
+                let register = self.next_register()?;
+                self.next_16_bits()?;
+                self.registers[register] = self.sp as i32;
+            }
+            Opcode::MTSP => {
+                let sp = self.registers[self.next_register()?];
+                self.next_16_bits()?;
+                let sp = usize::try_from(sp).map_err(|_| VmErrorKind::StackUnderflow)?;
+                if sp > self.stack.len() {
+                    return Err(VmErrorKind::StackOverflow {
+                        size: self.stack.len(),
+                    });
+                }
+                self.sp = sp;
+            }
+            Opcode::LOADF64 => {
+                let register = self.next_float_register()?;
+                let index = self.next_16_bits()?;
+                let value = *self
+                    .float_constants
+                    .get(index as usize)
+                    .ok_or(VmErrorKind::InvalidConstant { index })?;
+                self.float_registers[register] = value;
+            }
+            Opcode::ADDF64 => {
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.float_registers[self.next_float_register()?] = register1 + register2;
+            }
+            Opcode::SUBF64 => {
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.float_registers[self.next_float_register()?] = register1 - register2;
+            }
+            Opcode::MULF64 => {
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.float_registers[self.next_float_register()?] = register1 * register2;
+            }
+            Opcode::DIVF64 => {
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.float_registers[self.next_float_register()?] = register1 / register2;
+            }
+            Opcode::EQF64 => {
+                let (register1, register2) = self.compare_float()?;
+                self.flags.equal = register1 == register2;
+            }
+            Opcode::NEQF64 => {
+                let (register1, register2) = self.compare_float()?;
+                self.flags.equal = register1 != register2;
+            }
+            Opcode::GTF64 => {
+                let (register1, register2) = self.compare_float()?;
+                self.flags.equal = register1 > register2;
+            }
+            Opcode::GTEF64 => {
+                let (register1, register2) = self.compare_float()?;
+                self.flags.equal = register1 >= register2;
+            }
+            Opcode::LTF64 => {
+                let (register1, register2) = self.compare_float()?;
+                self.flags.equal = register1 < register2;
+            }
+            Opcode::LTEF64 => {
+                let (register1, register2) = self.compare_float()?;
+                self.flags.equal = register1 <= register2;
+            }
+            Opcode::ITOF => {
+                let register = self.next_float_register()?;
+                let value = self.registers[self.next_register()?];
+                self.next_8_bits()?;
+                self.float_registers[register] = value as f64;
+            }
+            Opcode::FTOI => {
+                let register = self.next_register()?;
+                let value = self.float_registers[self.next_float_register()?];
+                self.next_8_bits()?;
+                // `as` saturates out-of-range values and maps NaN to 0
+                self.registers[register] = value as i32;
+            }
+            Opcode::AND => {
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+                self.set_logic_result(register1 & register2)?;
+            }
+            Opcode::OR => {
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+                self.set_logic_result(register1 | register2)?;
+            }
+            Opcode::XOR => {
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+                self.set_logic_result(register1 ^ register2)?;
+            }
+            Opcode::NOT => {
+                let register = self.registers[self.next_register()?];
+                self.set_logic_result(!register)?;
+                self.next_8_bits()?;
+            }
+            Opcode::SHL => {
+                let value = self.registers[self.next_register()?];
+                let amount = self.registers[self.next_register()?];
+                self.set_logic_result(arithmetic::shl(value, amount as u32))?;
+            }
+            Opcode::SHR => {
+                let value = self.registers[self.next_register()?];
+                let amount = self.registers[self.next_register()?];
+                self.set_logic_result(arithmetic::shr(value, amount as u32))?;
+            }
+            Opcode::SAR => {
+                let value = self.registers[self.next_register()?];
+                let amount = self.registers[self.next_register()?];
+                self.set_logic_result(arithmetic::sar(value, amount as u32))?;
+            }
+            Opcode::SHLI => {
+                let value = self.registers[self.next_register()?];
+                let register = self.next_register()?;
+                let amount = self.next_8_bits()?;
+                self.registers[register] = arithmetic::shl(value, amount as u32);
+                self.flags.set_logic(self.registers[register]);
+            }
+            Opcode::SHRI => {
+                let value = self.registers[self.next_register()?];
+                let register = self.next_register()?;
+                let amount = self.next_8_bits()?;
+                self.registers[register] = arithmetic::shr(value, amount as u32);
+                self.flags.set_logic(self.registers[register]);
+            }
+            Opcode::SARI => {
+                let value = self.registers[self.next_register()?];
+                let register = self.next_register()?;
+                let amount = self.next_8_bits()?;
+                self.registers[register] = arithmetic::sar(value, amount as u32);
+                self.flags.set_logic(self.registers[register]);
+            }
+            Opcode::LUI => {
+                let register = self.next_register()?;
+                let number = self.next_16_bits()?;
+                self.registers[register] = ((number as u32) << 16) as i32;
+            }
+            Opcode::ORI => {
+                let register = self.next_register()?;
+                let number = self.next_16_bits()?;
+                self.registers[register] |= number as i32;
+            }
+            Opcode::ADDI => {
+                let register = self.next_register()?;
+                let (value, immediate) = (self.registers[register], self.next_16_bits()? as i16);
+                let result = self.arithmetic_mode.add(value, immediate as i32)?;
+                self.flags.set_add(value, immediate as i32, result);
+                self.registers[register] = result;
+            }
+            Opcode::SUBI => {
+                let register = self.next_register()?;
+                let (value, immediate) = (self.registers[register], self.next_16_bits()? as i16);
+                let result = self.arithmetic_mode.sub(value, immediate as i32)?;
+                self.flags.set_sub(value, immediate as i32, result);
+                self.registers[register] = result;
+            }
+            Opcode::MULI => {
+                let register = self.next_register()?;
+                let (value, immediate) = (self.registers[register], self.next_16_bits()? as i16);
+                let result = self.arithmetic_mode.mul(value, immediate as i32)?;
+                self.flags.set_mul(value, immediate as i32, result);
+                self.registers[register] = result;
+            }
+            Opcode::MOD => {
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+                if register2 == 0 {
+                    return Err(VmErrorKind::DivideByZero);
+                }
+
+                // i32::MIN % -1 is 0 but overflows in Rust's `%`, hence wrapping_rem
+                let result = register1.wrapping_rem(register2);
+                self.flags.set_rem(result);
+                self.registers[self.next_register()?] = result;
+            }
+            Opcode::MFR => {
+                let register = self.next_register()?;
+                self.next_16_bits()?;
+                self.registers[register] = self.remainder;
+            }
+            Opcode::IGL => {
+                return Err(VmErrorKind::IllegalOpcode);
+            }
+        };
+
+        Ok(ExitReason::Yielded)
+    }
+
+    /// Reads the first and last register of a PUSHM/POPM range and skips the padding byte
+    fn register_range(&mut self) -> Result<(usize, usize), VmErrorKind> {
+        let first = self.next_register()?;
+        let last = self.next_register()?;
+        self.next_8_bits()?;
+        if first > last {
+            return Err(VmErrorKind::InvalidRegister { index: last as u8 });
+        }
+        Ok((first, last))
+    }
+
+    /// Checks that `count` more values fit on the data stack
+    fn reserve_stack(&mut self, count: usize) -> Result<(), VmErrorKind> {
+        if self.sp + count > self.stack.len() {
+            return Err(VmErrorKind::StackOverflow {
+                size: self.stack.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Drops `count` values from the top of the data stack, leaving `sp` pointing at the lowest
+    fn release_stack(&mut self, count: usize) -> Result<(), VmErrorKind> {
+        if count > self.sp {
+            return Err(VmErrorKind::StackUnderflow);
+        }
+        self.sp -= count;
+        Ok(())
+    }
+
+    /// Stores the result of a bitwise or shift operation in the destination register operand
+    fn set_logic_result(&mut self, result: i32) -> Result<(), VmErrorKind> {
+        self.registers[self.next_register()?] = result;
+        self.flags.set_logic(result);
+        Ok(())
+    }
+
+    /// Spends the fuel for one instruction. Nothing is spent, and the instruction does not run,
+    /// if there is not enough left.
+    fn charge_fuel(&mut self, opcode: Opcode) -> Result<(), VmErrorKind> {
+        let cost = self.cost_table.cost(opcode);
+        if let Some(remaining) = self.fuel.as_mut() {
+            if *remaining < cost {
+                return Err(VmErrorKind::OutOfFuel {
+                    cost,
+                    remaining: *remaining,
+                });
+            }
+            *remaining -= cost;
+        }
+        // Unmetered VMs still count fuel, which must not overflow with a huge cost table
+        self.fuel_consumed = self.fuel_consumed.saturating_add(cost);
+        Ok(())
+    }
+
+    /// Reads the two register operands of a comparison and sets Z/N/C/V as if they were subtracted
+    fn compare(&mut self) -> Result<(i32, i32), VmErrorKind> {
+        let register1 = self.registers[self.next_register()?];
+        let register2 = self.registers[self.next_register()?];
+        self.next_8_bits()?;
+        self.flags
+            .set_sub(register1, register2, register1.wrapping_sub(register2));
+        Ok((register1, register2))
+    }
+
+    /// Reads the register operand of a jump and skips the unused operand bytes, leaving the
+    /// program counter on the next instruction
+    fn jump_operand(&mut self) -> Result<i32, VmErrorKind> {
+        let value = self.registers[self.next_register()?];
+        self.next_16_bits()?;
+        Ok(value)
+    }
+
+    /// Pushes a frame returning to the instruction after the current one and jumps to `target`
+    fn call(&mut self, target: i64) -> Result<(), VmErrorKind> {
+        if self.call_stack.len() >= self.max_call_depth {
+            return Err(VmErrorKind::CallStackOverflow {
+                depth: self.call_stack.len(),
+            });
+        }
+        let return_address = self.pc;
+        self.jump_to(target)?;
+        self.call_stack.push(Frame {
+            call_site: return_address - INSTRUCTION_SIZE,
+            entry: self.pc,
+            return_address,
+        });
+        Ok(())
+    }
+
+    /// Reads the two float register operands of a comparison and skips the padding byte
+    fn compare_float(&mut self) -> Result<(f64, f64), VmErrorKind> {
+        let register1 = self.float_registers[self.next_float_register()?];
+        let register2 = self.float_registers[self.next_float_register()?];
+        self.next_8_bits()?;
+        Ok((register1, register2))
+    }
+
+    /// Moves the program counter to `target`, which must be the start of an instruction or the
+    /// end of the program
+    fn jump_to(&mut self, target: i64) -> Result<(), VmErrorKind> {
+        if target < 0 || target > self.program.len() as i64 {
+            return Err(VmErrorKind::PcOutOfBounds { target });
+        }
+        if target % INSTRUCTION_SIZE as i64 != 0 {
+            return Err(VmErrorKind::MisalignedJump { target });
+        }
+        self.pc = target as usize;
+        Ok(())
+    }
+
+    pub fn get_test_vm() -> VM {
+        VM::new()
+    }
+
+    pub fn decode_opcode(&mut self) -> Opcode {
+        match self.next_8_bits() {
+            Ok(byte) => Opcode::from(byte),
+            Err(_) => Opcode::IGL,
+        }
+    }
+
+    pub fn next_8_bits(&mut self) -> Result<u8, VmErrorKind> {
+        let result = *self
+            .program
+            .get(self.pc)
+            .ok_or(VmErrorKind::TruncatedInstruction)?;
+        self.pc += 1;
+        Ok(result)
+    }
+
+    pub fn next_16_bits(&mut self) -> Result<u16, VmErrorKind> {
+        let high = self.next_8_bits()? as u16;
+        let low = self.next_8_bits()? as u16;
+        Ok((high << 8) | low)
+    }
+
+    /// Reads a base register and 8-bit offset operand pair and checks that `width` bytes starting
+    /// at the resulting address are inside the heap
+    fn next_heap_address(&mut self, width: usize) -> Result<usize, VmErrorKind> {
+        let base = self.registers[self.next_register()?];
+        let offset = self.next_8_bits()?;
+        let address = base as i64 + offset as i64;
+        if address < 0 || address + width as i64 > self.heap.len() as i64 {
+            return Err(VmErrorKind::HeapOutOfBounds {
+                address,
+                len: self.heap.len(),
+            });
+        }
+        Ok(address as usize)
+    }
+
+    /// Reads a base register and 8-bit offset operand pair and checks that `width` bytes starting
+    /// at the resulting address are inside the read-only data area
+    fn next_data_address(&mut self, width: usize) -> Result<usize, VmErrorKind> {
+        let base = self.registers[self.next_register()?];
+        let offset = self.next_8_bits()?;
+        let address = base as i64 + offset as i64;
+        if address < 0 || address + width as i64 > self.ro_data.len() as i64 {
+            return Err(VmErrorKind::DataOutOfBounds {
+                address,
+                len: self.ro_data.len(),
+            });
+        }
+        Ok(address as usize)
+    }
+
+    /// Reads a register operand, checking that the register exists
+    fn next_register(&mut self) -> Result<usize, VmErrorKind> {
+        let index = self.next_8_bits()?;
+        if index as usize >= self.registers.len() {
+            return Err(VmErrorKind::InvalidRegister { index });
+        }
+        Ok(index as usize)
+    }
+
+    /// Reads a float register operand, checking that the register exists
+    fn next_float_register(&mut self) -> Result<usize, VmErrorKind> {
+        let index = self.next_8_bits()?;
+        if index as usize >= self.float_registers.len() {
+            return Err(VmErrorKind::InvalidRegister { index });
+        }
+        Ok(index as usize)
+    }
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_vm() {
+        let test_vm = VM::new();
+        assert_eq!(test_vm.registers[0], 0);
+    }
+
+    #[test]
+    fn test_hlt_opcode() {
+        let mut test_vm = VM::new();
+        let test_bytes = vec![Opcode::HLT as u8, 0, 0, 0];
+        test_vm.program = test_bytes;
+        assert_eq!(test_vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(test_vm.pc, 4);
+    }
+
+    #[test]
+    fn test_igl_opcode() {
+        let mut test_vm = VM::new();
+        let test_bytes = vec![200, 0, 0, 0];
+        test_vm.program = test_bytes;
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::IllegalOpcode);
+        assert_eq!(error.pc, 0);
+        assert_eq!(error.opcode, 200);
+        assert_eq!(test_vm.pc, 1);
+    }
+
+    #[test]
+    fn test_load_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::LOAD as u8, 0, 1, 244]; // 用两个 u8 类型的数据，组成小端格式，以表达 500
+
+        assert_eq!(test_vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(test_vm.registers[0], 500);
+    }
+
+    #[test]
+    fn test_jmp_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 4; // JMP target
+        test_vm.program = vec![Opcode::JMP as u8, 0, 0, 0];
+        test_vm.run_once().unwrap();
+
+        assert_eq!(test_vm.pc, 4);
+    }
+
+    #[test]
+    fn test_jmpf_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 4; // JMPF offset, skips the HLT after the jump
+        test_vm.program = vec![Opcode::JMPF as u8, 0, 0, 0, Opcode::HLT as u8, 0, 0, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 8);
+
+        test_vm.registers[0] = 0; // an offset of 0 lands on the next instruction
+        test_vm.pc = 0;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 4);
+    }
+
+    #[test]
+    fn test_jmpb_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 8; // JMPB offset, back to the start of the program
+        test_vm.program = vec![Opcode::NOP as u8, 0, 0, 0, Opcode::JMPB as u8, 0, 0, 0];
+        test_vm.run_once().unwrap();
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 0);
+
+        test_vm.registers[0] = -4; // negative offsets jump the other way
+        test_vm
+            .program
+            .extend([Opcode::HLT as u8, 0, 0, 0, Opcode::HLT as u8, 0, 0, 0]);
+        test_vm.pc = 4;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+    }
+
+    #[test]
+    fn test_eq_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 10;
+        test_vm.registers[1] = 10;
+
+        test_vm.program = vec![Opcode::EQ as u8, 0, 1, 0, Opcode::EQ as u8, 0, 1, 0];
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+
+        test_vm.registers[1] = 20;
+        test_vm.run_once().unwrap();
+        assert!(!test_vm.flags.equal);
+    }
+
+    #[test]
+    fn test_neq_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 10;
+        test_vm.registers[1] = 20;
+        test_vm.program = vec![Opcode::NEQ as u8, 0, 1, 0, Opcode::NEQ as u8, 0, 1, 0];
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+
+        test_vm.registers[1] = 10;
+        test_vm.run_once().unwrap();
+        assert!(!test_vm.flags.equal);
+    }
+
+    #[test]
+    fn test_gte_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 20;
+        test_vm.registers[1] = 10;
+        test_vm.program = vec![
+            Opcode::GTE as u8,
+            0,
+            1,
+            0,
+            Opcode::GTE as u8,
+            0,
+            1,
+            0,
+            Opcode::GTE as u8,
+            0,
+            1,
+            0,
+        ];
+
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+
+        test_vm.registers[0] = 10;
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+
+        test_vm.registers[0] = 5;
+        test_vm.run_once().unwrap();
+        assert!(!test_vm.flags.equal);
+    }
+
+    #[test]
+    fn test_lte_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 20;
+        test_vm.registers[1] = 10;
+        test_vm.program = vec![
+            Opcode::LTE as u8,
+            0,
+            1,
+            0,
+            Opcode::LTE as u8,
+            0,
+            1,
+            0,
+            Opcode::LTE as u8,
+            0,
+            1,
+            0,
+        ];
+        test_vm.run_once().unwrap();
+        assert!(!test_vm.flags.equal);
+        test_vm.registers[0] = 10;
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+        test_vm.registers[0] = 5;
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+    }
+
+    #[test]
+    fn test_lt_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 20;
+        test_vm.registers[1] = 10;
+        test_vm.program = vec![
+            Opcode::LT as u8,
+            0,
+            1,
+            0,
+            Opcode::LT as u8,
+            0,
+            1,
+            0,
+            Opcode::LT as u8,
+            0,
+            1,
+            0,
+        ];
+        test_vm.run_once().unwrap();
+        assert!(!test_vm.flags.equal);
+        test_vm.registers[0] = 10;
+        test_vm.run_once().unwrap();
+        assert!(!test_vm.flags.equal);
+        test_vm.registers[0] = 5;
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+    }
+
+    #[test]
+    fn test_gt_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 20;
+        test_vm.registers[1] = 10;
+        test_vm.program = vec![
+            Opcode::GT as u8,
+            0,
+            1,
+            0,
+            Opcode::GT as u8,
+            0,
+            1,
+            0,
+            Opcode::GT as u8,
+            0,
+            1,
+            0,
+        ];
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+        test_vm.registers[0] = 10;
+        test_vm.run_once().unwrap();
+        assert!(!test_vm.flags.equal);
+        test_vm.registers[0] = 5;
+        test_vm.run_once().unwrap();
+        assert!(!test_vm.flags.equal);
+    }
+
+    #[test]
+    fn test_jmpe_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 8;
+        test_vm.flags.equal = true;
+        test_vm.program = vec![
+            Opcode::JMPE as u8,
+            0,
+            0,
+            0,
+            Opcode::JMPE as u8,
+            0,
+            0,
+            0,
+            Opcode::JMPE as u8,
+            0,
+            0,
+            0,
+        ];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 8);
+
+        test_vm.flags.equal = false;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+    }
+
+    #[test]
+    fn test_jmpne_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 8;
+        test_vm.program = vec![
+            Opcode::JMPNE as u8,
+            0,
+            0,
+            0,
+            Opcode::JMPNE as u8,
+            0,
+            0,
+            0,
+            Opcode::HLT as u8,
+            0,
+            0,
+            0,
+        ];
+        test_vm.flags.equal = true;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 4);
+
+        test_vm.flags.equal = false;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 8);
+    }
+
+    #[test]
+    fn test_misaligned_jump() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 3;
+        test_vm.program = vec![Opcode::JMP as u8, 0, 0, 0, Opcode::HLT as u8, 0, 0, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::MisalignedJump { target: 3 });
+    }
+
+    #[test]
+    fn test_countdown_loop() {
+        // $0 = 5, $1 = 1, $2 = loop start
+        // loop: SUB $0 $1 $0; ADD $4 $1 $4; EQ $0 $3; JMPNE $2; HLT
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![
+            Opcode::LOAD as u8,
+            0,
+            0,
+            5,
+            Opcode::LOAD as u8,
+            1,
+            0,
+            1,
+            Opcode::LOAD as u8,
+            2,
+            0,
+            12,
+            Opcode::SUB as u8,
+            0,
+            1,
+            0,
+            Opcode::ADD as u8,
+            4,
+            1,
+            4,
+            Opcode::EQ as u8,
+            0,
+            3,
+            0,
+            Opcode::JMPNE as u8,
+            2,
+            0,
+            0,
+            Opcode::HLT as u8,
+            0,
+            0,
+            0,
+        ];
+        assert_eq!(test_vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(test_vm.registers[0], 0);
+        assert_eq!(test_vm.registers[4], 5);
+    }
+
+    #[test]
+    fn test_aloc_opcode() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 1024;
+        test_vm.program = vec![17, 0, 0, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.heap.len(), 1024);
+        assert_eq!(test_vm.pc, INSTRUCTION_SIZE);
+    }
+
+    #[test]
+    fn test_truncated_instruction() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::NOP as u8, 0, 0, 0, Opcode::LOAD as u8, 0, 1];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::TruncatedInstruction);
+        assert_eq!(error.pc, 4);
+        assert_eq!(error.opcode, Opcode::LOAD as u8);
+    }
+
+    #[test]
+    fn test_invalid_register() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::ADD as u8, 0, 32, 1];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::InvalidRegister { index: 32 });
+    }
+
+    #[test]
+    fn test_div_by_zero() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 8;
+        test_vm.program = vec![Opcode::DIV as u8, 0, 1, 2];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::DivideByZero);
+        assert_eq!(error.opcode, Opcode::DIV as u8);
+    }
+
+    #[test]
+    fn test_jump_past_end_of_program() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 100;
+        test_vm.program = vec![Opcode::JMP as u8, 0, 0, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::PcOutOfBounds { target: 100 });
+        assert_eq!(error.pc, 0);
+    }
+
+    #[test]
+    fn test_aloc_negative() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = -1;
+        test_vm.program = vec![Opcode::ALOC as u8, 0, 0, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmErrorKind::HeapOutOfBounds {
+                address: -1,
+                len: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_aloc_heap_limit() {
+        let mut test_vm = VM::get_test_vm().with_heap_limit(16);
+        test_vm.registers[0] = 17;
+        test_vm.program = vec![Opcode::ALOC as u8, 0, 0, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmErrorKind::HeapLimitExceeded {
+                requested: 17,
+                limit: 16
+            }
+        );
+        assert_eq!(test_vm.heap.len(), 0);
+    }
+
+    #[test]
+    fn test_div_min_by_minus_one() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = i32::MIN;
+        test_vm.registers[1] = -1;
+        test_vm.program = vec![Opcode::DIV as u8, 0, 1, 2];
+        assert_eq!(test_vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(test_vm.registers[2], i32::MIN);
+    }
+
+    #[test]
+    fn test_random_programs_do_not_panic() {
+        // Small xorshift generator so the test is deterministic without extra dependencies
+        let mut state: u32 = 0x9e37_79b9;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        for _ in 0..5000 {
+            let len = (next() % 64) as usize;
+            let mut test_vm = VM::get_test_vm()
+                .with_heap_limit(4096)
+                .with_stack_size(16)
+                .with_max_call_depth(8);
+            // Every opcode byte is possible, but operands are mostly small so that register
+            // operands usually name a real register
+            test_vm.program = (0..len)
+                .map(|index| match (index % INSTRUCTION_SIZE, next() % 4) {
+                    (0, _) | (_, 0) => next() as u8,
+                    _ => (next() % 40) as u8,
+                })
+                .collect();
+            // Mostly small, aligned values, so that heap, data and jump addresses are in range
+            for register in test_vm.registers.iter_mut() {
+                *register = match next() % 4 {
+                    0 => next() as i32,
+                    1 => (next() % 16) as i32 - 8,
+                    _ => (next() % 16 * INSTRUCTION_SIZE as u32) as i32,
+                };
+            }
+            // Start with something to read so loads and pops get past their bounds checks
+            test_vm.heap = vec![0x5a; 64];
+            test_vm.sp = 4;
+            test_vm.ro_data = vec![0xa5; 64];
+            test_vm.float_constants = vec![0.5, -1.0];
+            test_vm.run_for(256);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_modes() {
+        let program = vec![Opcode::ADD as u8, 0, 1, 2];
+
+        let mut test_vm = VM::get_test_vm().with_arithmetic_mode(ArithmeticMode::Saturating);
+        test_vm.registers[0] = i32::MAX;
+        test_vm.registers[1] = 1;
+        test_vm.program = program.clone();
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[2], i32::MAX);
+
+        let mut test_vm = VM::get_test_vm().with_arithmetic_mode(ArithmeticMode::Trapping);
+        test_vm.registers[0] = i32::MAX;
+        test_vm.registers[1] = 1;
+        test_vm.program = vec![Opcode::NOP as u8, 0, 0, 0];
+        test_vm.program.extend(program);
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::ArithmeticOverflow);
+        assert_eq!(error.pc, 4);
+        assert_eq!(test_vm.registers[2], 0);
+    }
+
+    #[test]
+    fn test_arithmetic_sets_flags() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 5;
+        test_vm.registers[1] = 5;
+        test_vm.program = vec![Opcode::SUB as u8, 0, 1, 2];
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.zero);
+        assert!(!test_vm.flags.negative);
+
+        test_vm.registers[0] = i32::MAX;
+        test_vm.registers[1] = 1;
+        test_vm.program = vec![Opcode::ADD as u8, 0, 1, 2];
+        test_vm.pc = 0;
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.overflow);
+        assert!(test_vm.flags.negative);
+        assert!(!test_vm.flags.zero);
+    }
+
+    #[test]
+    fn test_conditional_flag_jumps() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 12;
+        test_vm.program = vec![
+            Opcode::JZ as u8,
+            0,
+            0,
+            0,
+            Opcode::JNZ as u8,
+            0,
+            0,
+            0,
+            Opcode::HLT as u8,
+            0,
+            0,
+            0,
+        ];
+        test_vm.flags.zero = false;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 4);
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+    }
+
+    #[test]
+    fn test_comparison_sets_signed_flags() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = -3;
+        test_vm.registers[1] = 2;
+        test_vm.registers[2] = 12;
+        test_vm.program = vec![
+            Opcode::LT as u8,
+            0,
+            1,
+            0,
+            Opcode::JLT as u8,
+            2,
+            0,
+            0,
+            Opcode::HLT as u8,
+            0,
+            0,
+            0,
+        ];
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+        assert!(test_vm.flags.less());
+        assert!(!test_vm.flags.carry);
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+    }
+
+    #[test]
+    fn test_heap_store_and_load() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.heap = vec![0; 8];
+        test_vm.registers[0] = -2; // value
+        test_vm.registers[1] = 2; // base address
+        test_vm.program = vec![
+            Opcode::STOREW as u8,
+            0,
+            1,
+            2,
+            Opcode::LOADW as u8,
+            2,
+            1,
+            2,
+            Opcode::LOADH as u8,
+            3,
+            1,
+            4,
+            Opcode::LOADB as u8,
+            4,
+            1,
+            5,
+            Opcode::STOREB as u8,
+            1,
+            1,
+            0,
+            Opcode::STOREH as u8,
+            1,
+            1,
+            1,
+        ];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.heap, vec![0, 0, 2, 0, 2, 0xff, 0xff, 0xfe]);
+        assert_eq!(test_vm.registers[2], -2);
+        assert_eq!(test_vm.registers[3], 0xfffe);
+        assert_eq!(test_vm.registers[4], 0xfe);
+    }
+
+    #[test]
+    fn test_load_executable_resets_state() {
+        let mut test_vm = VM::get_test_vm().with_stack_size(8).with_max_call_depth(4);
+        test_vm.registers[0] = 16;
+        test_vm.float_constants = vec![1.5];
+        // ALOC $0, PUSH $0, CALL 12, then stop inside the subroutine
+        test_vm.program = vec![
+            Opcode::ALOC as u8,
+            0,
+            0,
+            0,
+            Opcode::PUSH as u8,
+            0,
+            0,
+            0,
+            Opcode::CALL as u8,
+            0,
+            12,
+            0,
+            Opcode::NOP as u8,
+            0,
+            0,
+            0,
+        ];
+        assert_eq!(test_vm.run_for(3), RunStatus::Yielded);
+        assert_eq!(test_vm.call_stack().len(), 1);
+        assert_eq!(test_vm.sp(), 1);
+
+        let executable = Executable {
+            code: vec![Opcode::POP as u8, 1, 0, 0, Opcode::HLT as u8, 0, 0, 0],
+            float_constants: vec![0.5],
+            ..Executable::default()
+        };
+        test_vm
+            .load_executable(&executable.to_bytes().unwrap())
+            .unwrap();
+        assert!(test_vm.call_stack().is_empty());
+        assert_eq!(test_vm.sp(), 0);
+        assert!(test_vm.heap().is_empty());
+        assert_eq!(test_vm.registers, [0; REGISTER_COUNT]);
+        assert_eq!(test_vm.float_constants, vec![0.5]);
+        assert_eq!(test_vm.stack.len(), 8);
+        assert_eq!(test_vm.max_call_depth, 4);
+
+        // The previous program's stack is gone, so the POP underflows
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::StackUnderflow);
+    }
+
+    #[test]
+    fn test_read_only_data_loads() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.ro_data = vec![b'h', b'i', 0, 0xff, 0xff, 0xff, 0xfe];
+        test_vm.registers[1] = 1;
+        test_vm.program = vec![
+            Opcode::DLOADB as u8,
+            2,
+            1,
+            0,
+            Opcode::DLOADH as u8,
+            3,
+            1,
+            2,
+            Opcode::DLOADW as u8,
+            4,
+            1,
+            2,
+            Opcode::DLOADB as u8,
+            5,
+            1,
+            6,
+        ];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(test_vm.registers[2], b'i' as i32);
+        assert_eq!(test_vm.registers[3], 0xffff);
+        assert_eq!(test_vm.registers[4], -2);
+        assert_eq!(
+            error.kind,
+            VmErrorKind::DataOutOfBounds { address: 7, len: 7 }
+        );
+    }
+
+    #[test]
+    fn test_heap_access_out_of_bounds() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.heap = vec![0; 8];
+        test_vm.registers[1] = 6;
+        test_vm.program = vec![Opcode::LOADW as u8, 0, 1, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmErrorKind::HeapOutOfBounds { address: 6, len: 8 }
+        );
+
+        test_vm.registers[1] = -1;
+        test_vm.program = vec![Opcode::STOREB as u8, 0, 1, 0];
+        test_vm.pc = 0;
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmErrorKind::HeapOutOfBounds {
+                address: -1,
+                len: 8
+            }
+        );
+    }
+
+    #[test]
+    fn test_call_and_ret_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[1] = 16;
+        test_vm.program = vec![
+            Opcode::CALL as u8,
+            0,
+            12,
+            0,
+            Opcode::CALLR as u8,
+            1,
+            0,
+            0,
+            Opcode::HLT as u8,
+            0,
+            0,
+            0,
+            Opcode::ADD as u8,
+            0,
+            1,
+            0,
+            Opcode::RET as u8,
+            0,
+            0,
+            0,
+        ];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+        assert_eq!(
+            test_vm.call_stack(),
+            &[Frame {
+                call_site: 0,
+                entry: 12,
+                return_address: 4
+            }]
+        );
+
+        assert_eq!(test_vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(test_vm.registers[0], 16);
+        assert!(test_vm.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_call_stack_overflow() {
+        let mut test_vm = VM::get_test_vm().with_max_call_depth(3);
+        test_vm.program = vec![Opcode::CALL as u8, 0, 0, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::CallStackOverflow { depth: 3 });
+        assert_eq!(
+            error.to_string(),
+            "stack overflow (call depth 3) at pc 0 (opcode 35)"
+        );
+        assert_eq!(test_vm.call_stack().len(), 3);
+    }
+
+    #[test]
+    fn test_ret_without_call() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::RET as u8, 0, 0, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::CallStackUnderflow);
+    }
+
+    #[test]
+    fn test_push_pop_peek_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 7;
+        test_vm.registers[1] = -9;
+        test_vm.program = vec![
+            Opcode::PUSH as u8,
+            0,
+            0,
+            0,
+            Opcode::PUSH as u8,
+            1,
+            0,
+            0,
+            Opcode::PEEK as u8,
+            2,
+            0,
+            1,
+            Opcode::POP as u8,
+            3,
+            0,
+            0,
+        ];
+        test_vm.run_once().unwrap();
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.stack(), &[7, -9]);
+        assert_eq!(test_vm.sp(), 2);
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[2], 7);
+        assert_eq!(test_vm.registers[3], -9);
+        assert_eq!(test_vm.sp(), 1);
+    }
+
+    #[test]
+    fn test_pushm_popm_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[1] = 1;
+        test_vm.registers[2] = 2;
+        test_vm.registers[3] = 3;
+        test_vm.program = vec![Opcode::PUSHM as u8, 1, 3, 0, Opcode::POPM as u8, 4, 6, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.stack(), &[1, 2, 3]);
+        test_vm.run().unwrap();
+        assert_eq!(&test_vm.registers[4..7], &[1, 2, 3]);
+        assert_eq!(test_vm.sp(), 0);
+    }
+
+    #[test]
+    fn test_stack_overflow_and_underflow() {
+        let mut test_vm = VM::get_test_vm().with_stack_size(2);
+        test_vm.program = vec![Opcode::PUSHM as u8, 0, 2, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::StackOverflow { size: 2 });
+        assert_eq!(test_vm.sp(), 0);
+
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::POP as u8, 0, 0, 0];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::StackUnderflow);
+    }
+
+    #[test]
+    fn test_stack_pointer_opcodes() {
+        let mut test_vm = VM::get_test_vm().with_stack_size(4);
+        test_vm.registers[1] = 3;
+        test_vm.program = vec![
+            Opcode::MTSP as u8,
+            1,
+            0,
+            0,
+            Opcode::MFSP as u8,
+            2,
+            0,
+            0,
+            Opcode::MTSP as u8,
+            0,
+            0,
+            0,
+            Opcode::MFSP as u8,
+            3,
+            0,
+            0,
+        ];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[2], 3);
+        assert_eq!(test_vm.registers[3], 0);
+        assert_eq!(test_vm.sp(), 0);
+
+        for (sp, kind) in [
+            (5, VmErrorKind::StackOverflow { size: 4 }),
+            (-1, VmErrorKind::StackUnderflow),
+        ] {
+            let mut test_vm = VM::get_test_vm().with_stack_size(4);
+            test_vm.registers[0] = sp;
+            test_vm.program = vec![Opcode::MTSP as u8, 0, 0, 0];
+            assert_eq!(test_vm.run().unwrap_err().kind, kind);
+            assert_eq!(test_vm.sp(), 0);
+        }
+    }
+
+    #[test]
+    fn test_float_arithmetic_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.float_constants = vec![1.5, 0.25];
+        test_vm.program = vec![
+            Opcode::LOADF64 as u8,
+            0,
+            0,
+            0,
+            Opcode::LOADF64 as u8,
+            1,
+            0,
+            1,
+            Opcode::ADDF64 as u8,
+            0,
+            1,
+            2,
+            Opcode::SUBF64 as u8,
+            0,
+            1,
+            3,
+            Opcode::MULF64 as u8,
+            0,
+            1,
+            4,
+            Opcode::DIVF64 as u8,
+            0,
+            1,
+            5,
+        ];
+        test_vm.run().unwrap();
+        assert_eq!(
+            &test_vm.float_registers[..6],
+            &[1.5, 0.25, 1.75, 1.25, 0.375, 6.0]
+        );
+    }
+
+    #[test]
+    fn test_float_comparison_and_conversion_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.float_registers[0] = -2.75;
+        test_vm.float_registers[1] = 1.0;
+        test_vm.registers[0] = 3;
+        test_vm.program = vec![
+            Opcode::LTF64 as u8,
+            0,
+            1,
+            0,
+            Opcode::FTOI as u8,
+            1,
+            0,
+            0,
+            Opcode::ITOF as u8,
+            2,
+            0,
+            0,
+            Opcode::EQF64 as u8,
+            0,
+            1,
+            0,
+        ];
+        test_vm.run_once().unwrap();
+        assert!(test_vm.flags.equal);
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[1], -2);
+        assert_eq!(test_vm.float_registers[2], 3.0);
+        assert!(!test_vm.flags.equal);
+    }
+
+    #[test]
+    fn test_loadf64_invalid_constant() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::LOADF64 as u8, 0, 0, 4];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::InvalidConstant { index: 4 });
+    }
+
+    #[test]
+    fn test_bitwise_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = 0b1100;
+        test_vm.registers[1] = 0b1010;
+        test_vm.program = vec![
+            Opcode::AND as u8,
+            0,
+            1,
+            2,
+            Opcode::OR as u8,
+            0,
+            1,
+            3,
+            Opcode::XOR as u8,
+            0,
+            1,
+            4,
+            Opcode::NOT as u8,
+            0,
+            5,
+            0,
+        ];
+        test_vm.run().unwrap();
+        assert_eq!(&test_vm.registers[2..6], &[0b1000, 0b1110, 0b0110, !0b1100]);
+        assert!(test_vm.flags.negative);
+    }
+
+    #[test]
+    fn test_shift_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = -64;
+        test_vm.registers[1] = 4;
+        test_vm.registers[2] = 32;
+        test_vm.program = vec![
+            Opcode::SHL as u8,
+            0,
+            1,
+            3,
+            Opcode::SAR as u8,
+            0,
+            1,
+            4,
+            Opcode::SHR as u8,
+            0,
+            2,
+            5,
+            Opcode::SHRI as u8,
+            0,
+            6,
+            28,
+            Opcode::SARI as u8,
+            0,
+            7,
+            200,
+            Opcode::SHLI as u8,
+            1,
+            8,
+            1,
+        ];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[3], -1024);
+        assert_eq!(test_vm.registers[4], -4);
+        assert_eq!(test_vm.registers[5], 0);
+        assert_eq!(test_vm.registers[6], 0xf);
+        assert_eq!(test_vm.registers[7], -1);
+        assert_eq!(test_vm.registers[8], 8);
+    }
+
+    #[test]
+    fn test_lui_ori_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![
+            Opcode::LUI as u8,
+            0,
+            0xff,
+            0xfe,
+            Opcode::ORI as u8,
+            0,
+            0x1d,
+            0xc0,
+        ];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[0], -123456);
+    }
+
+    #[test]
+    fn test_immediate_arithmetic_opcodes() {
+        let mut test_vm = VM::get_test_vm().with_arithmetic_mode(ArithmeticMode::Trapping);
+        test_vm.registers[0] = 10;
+        test_vm.program = vec![
+            Opcode::ADDI as u8,
+            0,
+            0xff,
+            0xfd, // -3
+            Opcode::MULI as u8,
+            0,
+            0,
+            6,
+            Opcode::SUBI as u8,
+            0,
+            0,
+            42,
+            Opcode::LUI as u8,
+            1,
+            0x7f,
+            0xff,
+            Opcode::MULI as u8,
+            1,
+            0,
+            2,
+        ];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(test_vm.registers[0], 0);
+        assert!(test_vm.flags.zero);
+        assert_eq!(error.kind, VmErrorKind::ArithmeticOverflow);
+        assert_eq!(error.pc, 16);
+    }
+
+    #[test]
+    fn test_run_for_yields_and_resumes() {
+        // An infinite loop: ADD $0 $1 $0; JMP $2
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[1] = 1;
+        test_vm.program = vec![Opcode::ADD as u8, 0, 1, 0, Opcode::JMP as u8, 2, 0, 0];
+        assert_eq!(test_vm.run_for(5), RunStatus::Yielded);
+        assert_eq!(test_vm.registers[0], 3);
+        assert_eq!(test_vm.pc, 4);
+        assert_eq!(test_vm.run_for(0), RunStatus::Yielded);
+        assert_eq!(test_vm.run_for(1), RunStatus::Yielded);
+        assert_eq!(test_vm.pc, 0);
+        assert_eq!(test_vm.run_for(100), RunStatus::Yielded);
+        assert_eq!(test_vm.registers[0], 53);
+    }
+
+    #[test]
+    fn test_run_for_halts_and_faults() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::NOP as u8, 0, 0, 0, Opcode::HLT as u8, 0, 0, 0];
+        assert_eq!(test_vm.run_for(1), RunStatus::Yielded);
+        assert_eq!(test_vm.run_for(10), RunStatus::Halted(ExitReason::Halted));
+        assert_eq!(
+            test_vm.run_for(10),
+            RunStatus::Halted(ExitReason::EndOfProgram)
+        );
+
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::DIV as u8, 0, 1, 2];
+        match test_vm.run_for(10) {
+            RunStatus::Faulted(error) => assert_eq!(error.kind, VmErrorKind::DivideByZero),
+            status => panic!("expected a fault, got {:?}", status),
+        }
+    }
+
+    #[test]
+    fn test_fuel_metering() {
+        let costs = CostTable::new(1).with_cost(Opcode::DIV, 10);
+        let mut test_vm = VM::get_test_vm().with_fuel(12).with_cost_table(costs);
+        test_vm.registers[0] = 9;
+        test_vm.registers[1] = 3;
+        test_vm.program = vec![
+            Opcode::ADD as u8,
+            0,
+            1,
+            2,
+            Opcode::DIV as u8,
+            0,
+            1,
+            3,
+            Opcode::DIV as u8,
+            0,
+            1,
+            4,
+        ];
+        let error = test_vm.run().unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmErrorKind::OutOfFuel {
+                cost: 10,
+                remaining: 1
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "out of fuel (needs 10, 1 left) at pc 8 (opcode 4)"
+        );
+        assert_eq!(test_vm.fuel_consumed(), 11);
+        assert_eq!(test_vm.fuel_remaining(), Some(1));
+        assert_eq!(test_vm.registers[3], 3);
+        assert_eq!(test_vm.pc, 8);
+
+        // Refuelling resumes at the instruction that could not be paid for
+        test_vm.add_fuel(9);
+        assert_eq!(test_vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(test_vm.registers[4], 3);
+        assert_eq!(test_vm.fuel_consumed(), 10);
+        assert_eq!(test_vm.fuel_remaining(), Some(0));
+    }
+
+    #[test]
+    fn test_unmetered_vm_reports_fuel() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.program = vec![Opcode::NOP as u8, 0, 0, 0, Opcode::NOP as u8, 0, 0, 0];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.fuel_consumed(), 2);
+        assert_eq!(test_vm.fuel_remaining(), None);
+
+        let mut test_vm = VM::get_test_vm().with_cost_table(CostTable::new(u64::MAX));
+        test_vm.program = vec![Opcode::NOP as u8, 0, 0, 0, Opcode::NOP as u8, 0, 0, 0];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.fuel_consumed(), u64::MAX);
+    }
+
+    #[test]
+    fn test_mod_and_mfr_opcodes() {
+        let mut test_vm = VM::get_test_vm();
+        test_vm.registers[0] = -7;
+        test_vm.registers[1] = 2;
+        test_vm.program = vec![
+            Opcode::DIV as u8,
+            0,
+            1,
+            2,
+            Opcode::MFR as u8,
+            3,
+            0,
+            0,
+            Opcode::MOD as u8,
+            1,
+            0,
+            4,
+        ];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[2], -3);
+        assert_eq!(test_vm.remainder(), -1);
+        assert_eq!(test_vm.registers[3], -1);
+        assert_eq!(test_vm.registers[4], 2);
+
+        test_vm.registers[0] = i32::MIN;
+        test_vm.registers[1] = -1;
+        test_vm.program = vec![Opcode::MOD as u8, 0, 1, 5];
+        test_vm.pc = 0;
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[5], 0);
+        assert!(test_vm.flags.zero);
+    }
+}